@@ -0,0 +1,128 @@
+use crate::push_json_string;
+use crate::sink::RunInfo;
+use chrono::{DateTime, Local, SecondsFormat};
+use std::env;
+use std::ffi::CStr;
+use std::time::SystemTime;
+
+/// Where --banner lines are written
+#[derive(Clone, Copy, PartialEq)]
+pub enum BannerTarget {
+    Files,
+    // Stdout as well as the output files
+    All,
+}
+
+impl BannerTarget {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "files" => Some(BannerTarget::Files),
+            "all" => Some(BannerTarget::All),
+            _ => None,
+        }
+    }
+}
+
+/// Lines before the first and after the last record that record who ran
+/// what, where, and how it ended. Text banners start with "# " so they are
+/// easy to filter out; JSON banners carry a "banner" field instead of a
+/// "timestamp", so replay skips them.
+pub struct Banner {
+    pub target: BannerTarget,
+    json: bool,
+    start: SystemTime,
+}
+
+impl Banner {
+    pub fn new(target: BannerTarget, json: bool, start: SystemTime) -> Self {
+        Self { target, json, start }
+    }
+
+    pub fn start_line(&self, run: &RunInfo) -> Vec<u8> {
+        let version = env!("CARGO_PKG_VERSION");
+        let started = iso(self.start);
+        let user = username();
+        let cwd = env::current_dir()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_else(|_| "?".to_string());
+
+        let mut out = Vec::new();
+        if self.json {
+            out.extend_from_slice(b"{\"banner\":\"start\",\"version\":");
+            push_json_string(&mut out, version);
+            for (key, value) in [("started", &started), ("host", &run.host), ("user", &user), ("cwd", &cwd)] {
+                out.extend_from_slice(format!(",\"{}\":", key).as_bytes());
+                push_json_string(&mut out, value);
+            }
+            if let Some(ref command) = run.command {
+                out.extend_from_slice(b",\"command\":");
+                push_json_string(&mut out, command);
+            }
+            out.extend_from_slice(b"}\n");
+        } else {
+            let mut line = format!("# tss {} started {} on {} by {} in {}", version, started, run.host, user, cwd);
+            if let Some(ref command) = run.command {
+                line.push_str(": ");
+                line.push_str(command);
+            }
+            line.push('\n');
+            out.extend_from_slice(line.as_bytes());
+        }
+        out
+    }
+
+    /// `lines` counts input lines, `exit_status` is what tss exits with
+    pub fn end_line(&self, end: SystemTime, lines: u64, exit_status: i32) -> Vec<u8> {
+        let finished = iso(end);
+        let elapsed = end.duration_since(self.start).unwrap_or_default().as_secs_f64();
+        if self.json {
+            let mut out = b"{\"banner\":\"end\",\"finished\":".to_vec();
+            push_json_string(&mut out, &finished);
+            out.extend_from_slice(
+                format!(",\"elapsed\":{:.6},\"lines\":{},\"exit_status\":{}}}\n", elapsed, lines, exit_status)
+                    .as_bytes(),
+            );
+            out
+        } else {
+            format!(
+                "# tss finished {} after {:.3}s: {} lines, exit status {}\n",
+                finished, elapsed, lines, exit_status
+            )
+            .into_bytes()
+        }
+    }
+}
+
+/// Whether `line` is a text banner, which replay and --stamp-again leave
+/// alone as it carries no timestamp prefix
+pub fn is_text_banner(line: &str) -> bool {
+    let Some(rest) = line.strip_prefix("# tss ") else {
+        return false;
+    };
+    rest.starts_with("finished ")
+        || rest
+            .split_once(' ')
+            .is_some_and(|(_, rest)| rest.starts_with("started "))
+}
+
+fn iso(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).to_rfc3339_opts(SecondsFormat::Millis, false)
+}
+
+/// Name of the user tss runs as, falling back to $USER and then the uid
+fn username() -> String {
+    // SAFETY: getuid has no preconditions
+    let uid = unsafe { libc::getuid() };
+    let mut buf = [0 as libc::c_char; 1024];
+    // SAFETY: passwd is plain data that getpwuid_r fills in
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    // SAFETY: every pointer is valid for the duration of the call and
+    // buf.len() is the size of buf
+    let ret = unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if ret == 0 && !result.is_null() {
+        // SAFETY: on success pw_name points to a NUL-terminated string in buf
+        return unsafe { CStr::from_ptr(passwd.pw_name) }.to_string_lossy().into_owned();
+    }
+    env::var("USER").unwrap_or_else(|_| uid.to_string())
+}
@@ -0,0 +1,114 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Clock the timestamps are read from. Readings are expressed as an offset
+/// from UNIX_EPOCH, so for the monotonic family an "epoch" timestamp is the
+/// time since boot.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Clock {
+    Realtime,
+    Monotonic,
+    MonotonicRaw,
+    Boottime,
+    Tai,
+}
+
+impl Clock {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let clock = match name {
+            "realtime" => Clock::Realtime,
+            "monotonic" => Clock::Monotonic,
+            "monotonic-raw" => Clock::MonotonicRaw,
+            "boottime" => Clock::Boottime,
+            "tai" => Clock::Tai,
+            _ => return None,
+        };
+        Some(clock)
+    }
+
+    /// Whether readings are calendar time that date formats can render
+    pub fn is_wall(self) -> bool {
+        matches!(self, Clock::Realtime | Clock::Tai)
+    }
+
+    pub fn now(self) -> SystemTime {
+        let id = match self {
+            Clock::Realtime => return SystemTime::now(),
+            Clock::Monotonic => libc::CLOCK_MONOTONIC,
+            Clock::MonotonicRaw => libc::CLOCK_MONOTONIC_RAW,
+            Clock::Boottime => libc::CLOCK_BOOTTIME,
+            Clock::Tai => libc::CLOCK_TAI,
+        };
+        UNIX_EPOCH + read(id)
+    }
+}
+
+/// Wall-clock time the system booted, for converting dmesg timestamps
+pub fn boot_time() -> SystemTime {
+    SystemTime::now() - read(libc::CLOCK_BOOTTIME)
+}
+
+/// Split a dmesg-style "[ 1234.567890] " prefix off `line`, returning the
+/// seconds since boot and the rest of the line
+pub fn split_dmesg(line: &str) -> Option<(Duration, &str)> {
+    let rest = line.strip_prefix('[')?;
+    let end = rest.find(']')?;
+    let (secs, frac) = rest[..end].trim_start().split_once('.')?;
+    if frac.is_empty() || frac.len() > 9 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let secs: u64 = secs.parse().ok()?;
+    let nanos = frac.parse::<u32>().ok()? * 10u32.pow(9 - frac.len() as u32);
+    let text = &rest[end + 1..];
+    Some((Duration::new(secs, nanos), text.strip_prefix(' ').unwrap_or(text)))
+}
+
+/// Wall-clock time of a dmesg timestamp, or None when it lies beyond what
+/// chrono and nanosecond epoch timestamps can represent (around 2262)
+pub fn dmesg_time(boot: SystemTime, since_boot: Duration) -> Option<SystemTime> {
+    let time = boot.checked_add(since_boot)?;
+    // A day of slack so no local UTC offset can push it out of range
+    let latest = time.checked_add(Duration::from_secs(86_400))?;
+    // Any i64 of nanoseconds is also a date chrono can render
+    i64::try_from(latest.duration_since(UNIX_EPOCH).ok()?.as_nanos()).ok()?;
+    Some(time)
+}
+
+fn read(id: libc::clockid_t) -> Duration {
+    // SAFETY: ts is valid for writes and every id used here exists on Linux
+    let mut ts: libc::timespec = unsafe { std::mem::zeroed() };
+    unsafe { libc::clock_gettime(id, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_dmesg_prefixes() {
+        assert_eq!(
+            split_dmesg("[    1.500000] usb 1-1: new device"),
+            Some((Duration::from_millis(1500), "usb 1-1: new device"))
+        );
+        assert_eq!(split_dmesg("[12345.000001]x"), Some((Duration::new(12345, 1_000), "x")));
+        assert_eq!(split_dmesg("[ 0.5] "), Some((Duration::from_millis(500), "")));
+    }
+
+    #[test]
+    fn leaves_other_brackets_alone() {
+        assert_eq!(split_dmesg("no prefix"), None);
+        assert_eq!(split_dmesg("[INFO] started"), None);
+        assert_eq!(split_dmesg("[12] no fraction"), None);
+        assert_eq!(split_dmesg("[1.] empty fraction"), None);
+        assert_eq!(split_dmesg("[1.0000000001] too precise"), None);
+        assert_eq!(split_dmesg("[1.5 unterminated"), None);
+    }
+
+    #[test]
+    fn dmesg_time_stays_in_range() {
+        let boot = UNIX_EPOCH + Duration::from_secs(1_792_385_859);
+        assert_eq!(dmesg_time(boot, Duration::from_secs(60)), Some(boot + Duration::from_secs(60)));
+        assert_eq!(dmesg_time(boot, Duration::from_secs(u64::MAX / 2)), None);
+        assert_eq!(dmesg_time(boot, Duration::from_secs(300 * 365 * 86_400)), None);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::sink::Stream;
+use crate::Stamp;
+use regex::Regex;
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+/// Collapses runs of consecutive identical lines from the same input or
+/// stream. Lines are compared after
+/// optionally masking digits and removing matches of an ignore pattern, so
+/// counters and timestamps embedded in retry messages don't break a run.
+pub struct Deduper {
+    ignore_digits: bool,
+    ignore: Option<Regex>,
+    // Stream and comparison key of the last line written
+    last_key: Option<(Stream, String)>,
+    last_instant: Option<Instant>,
+    last_timestamp: String,
+    run: Option<Repeat>,
+}
+
+/// A run of suppressed repeats of the last written line
+pub struct Repeat {
+    pub count: u64,
+    pub stream: Stream,
+    // Timestamp of the original line, which the duration is measured from
+    pub first_seen: String,
+    pub last_seen: String,
+    started: Instant,
+    ended: Stamp,
+}
+
+impl Repeat {
+    /// Time from the original line to the last repeat
+    pub fn duration(&self) -> Duration {
+        self.ended.mono.duration_since(self.started)
+    }
+
+    /// When the last repeat was read
+    pub fn last_stamp(&self) -> Stamp {
+        self.ended
+    }
+
+    pub fn message(&self) -> String {
+        format!(
+            "last message repeated {} times over {:.1}s",
+            self.count,
+            self.duration().as_secs_f64()
+        )
+    }
+}
+
+impl Deduper {
+    pub fn new(ignore_digits: bool, ignore: Option<Regex>) -> Self {
+        Self {
+            ignore_digits,
+            ignore,
+            last_key: None,
+            last_instant: None,
+            last_timestamp: String::new(),
+            run: None,
+        }
+    }
+
+    /// Returns true if `line` on `stream` repeats the previous line and
+    /// should be suppressed; the caller then reports it through
+    /// `add_repeat`. `timestamp` is what the line is written with otherwise.
+    pub fn is_repeat(&mut self, line: &str, stream: &Stream, timestamp: &str, stamp: Stamp) -> bool {
+        let key = self.key(line);
+        if self
+            .last_key
+            .as_ref()
+            .is_some_and(|(last_stream, last)| last_stream == stream && *last == key)
+        {
+            return true;
+        }
+        self.last_key = Some((stream.clone(), key.into_owned()));
+        self.last_instant = Some(stamp.mono);
+        self.last_timestamp.clear();
+        self.last_timestamp.push_str(timestamp);
+        false
+    }
+
+    pub fn add_repeat(&mut self, timestamp: &str, stamp: Stamp) {
+        match self.run {
+            Some(ref mut run) => {
+                run.count += 1;
+                run.last_seen.clear();
+                run.last_seen.push_str(timestamp);
+                run.ended = stamp;
+            }
+            None => {
+                self.run = Some(Repeat {
+                    count: 1,
+                    stream: self.last_key.as_ref().map_or(Stream::Stdin, |(stream, _)| stream.clone()),
+                    first_seen: self.last_timestamp.clone(),
+                    last_seen: timestamp.to_string(),
+                    started: self.last_instant.unwrap_or(stamp.mono),
+                    ended: stamp,
+                });
+            }
+        }
+    }
+
+    /// Take the pending run of repeats, if any, to be written out
+    pub fn take_repeat(&mut self) -> Option<Repeat> {
+        self.run.take()
+    }
+
+    fn key<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let mut key = Cow::Borrowed(line);
+        if let Some(ref re) = self.ignore {
+            if let Cow::Owned(s) = re.replace_all(&key, "") {
+                key = Cow::Owned(s);
+            }
+        }
+        if self.ignore_digits && key.bytes().any(|b| b.is_ascii_digit()) {
+            key = Cow::Owned(
+                key.chars()
+                    .map(|c| if c.is_ascii_digit() { '0' } else { c })
+                    .collect(),
+            );
+        }
+        key
+    }
+}
@@ -0,0 +1,116 @@
+use encoding_rs::{Decoder, EncoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use std::io::{self, Read};
+
+/// Decodes input in a legacy encoding or UTF-16 to UTF-8 as it is read, so
+/// lines are split and stamped as usual. A byte order mark switches to
+/// UTF-8 or UTF-16 regardless of the given encoding and is dropped.
+/// Malformed input becomes U+FFFD instead of failing the stream.
+pub struct Decoding<R> {
+    inner: R,
+    decoder: Decoder,
+    raw: Vec<u8>,
+    decoded: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Decoding<R> {
+    pub fn new(inner: R, encoding: &'static Encoding) -> Self {
+        Self {
+            inner,
+            decoder: encoding.new_decoder(),
+            raw: vec![0; 64 * 1024],
+            decoded: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> Read for Decoding<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            if self.done {
+                return Ok(0);
+            }
+            let n = self.inner.read(&mut self.raw)?;
+            // End of input flushes a trailing partial character
+            let last = n == 0;
+            let max = self
+                .decoder
+                .max_utf8_buffer_length(n)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::OutOfMemory, "decode buffer too large"))?;
+            self.decoded.resize(max, 0);
+            let (_, _, written, _) = self.decoder.decode_to_utf8(&self.raw[..n], &mut self.decoded, last);
+            self.decoded.truncate(written);
+            self.pos = 0;
+            self.done = last;
+        }
+        let n = buf.len().min(self.decoded.len() - self.pos);
+        buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Encoding for --input-encoding, by any WHATWG label (latin1, cp1252,
+/// utf-16le, shift_jis, ...)
+pub fn for_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.as_bytes())
+}
+
+/// Encoding of the output files
+#[derive(Clone, Copy)]
+pub enum OutputEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Legacy(&'static Encoding),
+}
+
+impl OutputEncoding {
+    pub fn for_label(label: &str) -> Option<Self> {
+        let encoding = for_label(label)?;
+        // encoding_rs only decodes UTF-16, so it is encoded here
+        Some(if encoding == UTF_8 {
+            OutputEncoding::Utf8
+        } else if encoding == UTF_16LE {
+            OutputEncoding::Utf16Le
+        } else if encoding == UTF_16BE {
+            OutputEncoding::Utf16Be
+        } else {
+            OutputEncoding::Legacy(encoding)
+        })
+    }
+
+    /// Encode `text` into `out`. Characters the encoding cannot represent
+    /// are written as '?'.
+    pub fn encode(self, text: &str, out: &mut Vec<u8>) {
+        out.clear();
+        match self {
+            OutputEncoding::Utf8 => out.extend_from_slice(text.as_bytes()),
+            OutputEncoding::Utf16Le => text.encode_utf16().for_each(|unit| out.extend_from_slice(&unit.to_le_bytes())),
+            OutputEncoding::Utf16Be => text.encode_utf16().for_each(|unit| out.extend_from_slice(&unit.to_be_bytes())),
+            OutputEncoding::Legacy(encoding) => {
+                let mut encoder = encoding.new_encoder();
+                let mut src = text;
+                loop {
+                    let start = out.len();
+                    let max = encoder
+                        .max_buffer_length_from_utf8_without_replacement(src.len())
+                        .unwrap_or(src.len() * 4);
+                    out.resize(start + max, 0);
+                    let (result, read, written) =
+                        encoder.encode_from_utf8_without_replacement(src, &mut out[start..], true);
+                    out.truncate(start + written);
+                    src = &src[read..];
+                    match result {
+                        EncoderResult::InputEmpty => break,
+                        EncoderResult::Unmappable(_) => out.push(b'?'),
+                        EncoderResult::OutputFull => {}
+                    }
+                }
+            }
+        }
+    }
+}
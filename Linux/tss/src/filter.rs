@@ -0,0 +1,48 @@
+use crate::theme::RESET;
+use regex::Regex;
+
+/// --match and --exclude: which records are shown. Hidden records are
+/// still timed, counted and handed to the sinks.
+#[derive(Default)]
+pub struct Filter {
+    pub matches: Vec<Regex>,
+    pub excludes: Vec<Regex>,
+}
+
+impl Filter {
+    pub fn passes(&self, line: &str) -> bool {
+        (self.matches.is_empty() || self.matches.iter().any(|re| re.is_match(line)))
+            && !self.excludes.iter().any(|re| re.is_match(line))
+    }
+}
+
+/// A --highlight rule: text matching `regex` is wrapped in the SGR `color`
+pub struct Highlight {
+    pub regex: Regex,
+    pub color: String,
+}
+
+/// Append `line` with every highlighted match colored. Where matches
+/// overlap, the rule given first wins.
+pub fn push_highlighted(out: &mut Vec<u8>, line: &str, rules: &[Highlight]) {
+    let mut spans: Vec<(usize, usize, &str)> = Vec::new();
+    for rule in rules {
+        for m in rule.regex.find_iter(line).filter(|m| !m.is_empty()) {
+            if !spans.iter().any(|&(start, end, _)| m.start() < end && start < m.end()) {
+                spans.push((m.start(), m.end(), &rule.color));
+            }
+        }
+    }
+    spans.sort_unstable_by_key(|&(start, _, _)| start);
+
+    let bytes = line.as_bytes();
+    let mut pos = 0;
+    for (start, end, color) in spans {
+        out.extend_from_slice(&bytes[pos..start]);
+        out.extend_from_slice(color.as_bytes());
+        out.extend_from_slice(&bytes[start..end]);
+        out.extend_from_slice(RESET.as_bytes());
+        pos = end;
+    }
+    out.extend_from_slice(&bytes[pos..]);
+}
@@ -0,0 +1,193 @@
+use crate::latency::format_seconds;
+use crate::sink::{Record, RunInfo, Sink};
+use crate::theme::strip_escapes;
+use chrono::{DateTime, Local};
+use regex::Regex;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::SystemTime;
+
+// Lines that open a new collapsible step: CI log groups, makepkg, ninja,
+// docker build and banner-style separators
+const DEFAULT_STEPS: &str = r"^(?:::group::|##\[group\]|==> |=== |--- |\[\d+/\d+\] |Step \d+/\d+|#\d+ \[)";
+
+const STYLE: &str = "
+body{font:13px/1.4 ui-monospace,SFMono-Regular,Menlo,monospace;margin:0;background:#fff;color:#222}
+header{position:sticky;top:0;background:#f6f6f6;border-bottom:1px solid #ddd;padding:8px 12px;z-index:1}
+h1{font-size:15px;margin:0 0 4px}
+.meta{color:#666}
+#search{width:24em;font:inherit;padding:2px 4px;margin-top:6px}
+#hits{color:#666;margin-left:8px}
+#chart{display:block;width:100%;height:48px;margin-top:6px;background:#fff;border:1px solid #ddd}
+#chart rect{fill:#4a7fc1;cursor:pointer}
+#chart rect:hover{fill:#e0862a}
+main{padding:4px 0}
+.l{display:flex;white-space:pre-wrap;background:rgba(255,140,0,calc(var(--g,0)*.55))}
+.l:hover{outline:1px solid #bbb}
+.t{color:#2a7ab0;flex:none;padding:0 8px 0 12px}
+.d{color:#888;flex:none;width:8em;text-align:right;padding-right:8px}
+.x{flex:1;word-break:break-all}
+details>summary{list-style:none;cursor:pointer;font-weight:bold;border-top:1px solid #eee}
+details>summary::-webkit-details-marker{display:none}
+details>summary .x::before{content:'\\25B8  '}
+details[open]>summary .x::before{content:'\\25BE  '}
+.s{flex:none;color:#666;font-weight:normal;padding-right:12px}
+.hide{display:none}
+footer{border-top:1px solid #ddd;padding:8px 12px;color:#666}
+";
+
+const SCRIPT: &str = "
+document.addEventListener('DOMContentLoaded',function(){
+  var rows=Array.prototype.slice.call(document.querySelectorAll('main .l'));
+  if(!rows.length)return;
+  var t=function(r){return +r.dataset.t};
+  var last=t(rows[rows.length-1]);
+  var dur=function(ms){var s=ms/1000;return s<60?s.toFixed(1)+'s':Math.floor(s/60)+'m '+('0'+Math.floor(s%60)).slice(-2)+'s'};
+  var steps=document.querySelectorAll('main details');
+  steps.forEach(function(d,i){
+    var start=t(d.querySelector('.l'));
+    var end=i+1<steps.length?t(steps[i+1].querySelector('.l')):last;
+    d.querySelector('.s').textContent=dur(end-start)+', '+d.querySelectorAll('.l').length+' lines';
+  });
+  var svg=document.getElementById('chart'),bins=120,first=t(rows[0]),span=Math.max(last-first,1);
+  var counts=new Array(bins).fill(0),firstRow=new Array(bins);
+  rows.forEach(function(r){var b=Math.min(bins-1,Math.floor((t(r)-first)/span*bins));counts[b]++;if(!firstRow[b])firstRow[b]=r});
+  var max=Math.max.apply(null,counts);
+  svg.setAttribute('viewBox','0 0 '+bins+' 100');svg.setAttribute('preserveAspectRatio','none');
+  counts.forEach(function(c,b){
+    if(!c)return;
+    var r=document.createElementNS('http://www.w3.org/2000/svg','rect'),h=Math.max(2,c/max*100);
+    r.setAttribute('x',b);r.setAttribute('y',100-h);r.setAttribute('width',.9);r.setAttribute('height',h);
+    var title=document.createElementNS('http://www.w3.org/2000/svg','title');
+    title.textContent=c+' lines from '+firstRow[b].querySelector('.t').textContent;r.appendChild(title);
+    r.addEventListener('click',function(){var d=firstRow[b].closest('details');if(d)d.open=true;firstRow[b].scrollIntoView({block:'center'})});
+    svg.appendChild(r);
+  });
+  var search=document.getElementById('search'),hits=document.getElementById('hits'),timer;
+  search.addEventListener('input',function(){clearTimeout(timer);timer=setTimeout(function(){
+    var q=search.value.toLowerCase(),n=0;
+    rows.forEach(function(r){var m=!q||r.textContent.toLowerCase().indexOf(q)>=0;r.classList.toggle('hide',!m);if(m)n++});
+    steps.forEach(function(d){var any=d.querySelector('.l:not(.hide)');d.classList.toggle('hide',!!q&&!any);if(q&&any)d.open=true;d.querySelector('summary .l').classList.remove('hide')});
+    hits.textContent=q?n+' matching lines':'';
+  },150)});
+});
+";
+
+/// Writes a single self-contained HTML page with every line, its delta,
+/// collapsible steps, a search box and a line density chart. Rows are
+/// streamed as they arrive; the script in the head fills in step durations
+/// and the chart when the page is opened, so no external assets are needed.
+pub struct HtmlSink {
+    out: BufWriter<File>,
+    steps: Regex,
+    in_step: bool,
+    lines: u64,
+    start: SystemTime,
+}
+
+impl HtmlSink {
+    pub fn create(path: &str, steps: Option<Regex>, run: &RunInfo) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let title = run.command.as_deref().unwrap_or("tss");
+        let started: DateTime<Local> = run.start.into();
+
+        out.write_all(b"<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>")?;
+        write_escaped(&mut out, title)?;
+        write!(out, "</title>\n<style>{}</style>\n<script>{}</script>\n</head><body>\n<header><h1>", STYLE, SCRIPT)?;
+        write_escaped(&mut out, title)?;
+        out.write_all(b"</h1><div class=\"meta\">")?;
+        write_escaped(&mut out, &run.host)?;
+        write!(out, " &middot; started {}", started.format("%Y-%m-%d %H:%M:%S %z"))?;
+        out.write_all(
+            b"</div>\n<input id=\"search\" type=\"search\" placeholder=\"Search\"><span id=\"hits\"></span>\n<svg id=\"chart\"></svg></header>\n<main>\n",
+        )?;
+
+        Ok(Self {
+            out,
+            steps: steps.unwrap_or_else(|| Regex::new(DEFAULT_STEPS).unwrap()),
+            in_step: false,
+            lines: 0,
+            start: run.start,
+        })
+    }
+
+    fn write_row(&mut self, record: &Record, text: &str) -> io::Result<()> {
+        let millis = record.epoch_nanos() as f64 / 1e6;
+        write!(self.out, "<div class=\"l\" data-t=\"{:.3}\"", millis)?;
+        let shade = shade(record.delta.as_secs_f64());
+        if shade > 0.0 {
+            write!(self.out, " style=\"--g:{:.2}\"", shade)?;
+        }
+        self.out.write_all(b"><span class=\"t\">")?;
+        write_escaped(&mut self.out, &record.timestamp)?;
+        write!(self.out, "</span><span class=\"d\">{}</span><span class=\"x\">", format_seconds(record.delta.as_nanos() as i64))?;
+        write_escaped(&mut self.out, text)?;
+        self.out.write_all(b"</span>")?;
+        Ok(())
+    }
+}
+
+impl Sink for HtmlSink {
+    fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        let text = strip_escapes(&record.text());
+        self.lines += record.lines.len() as u64;
+
+        if self.steps.is_match(&text) {
+            if self.in_step {
+                self.out.write_all(b"</details>\n")?;
+            }
+            self.in_step = true;
+            self.out.write_all(b"<details open><summary>")?;
+            self.write_row(record, &text)?;
+            self.out.write_all(b"<span class=\"s\"></span></div></summary>\n")?;
+        } else {
+            self.write_row(record, &text)?;
+            self.out.write_all(b"</div>\n")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, end: SystemTime, exit_code: Option<i32>) -> io::Result<()> {
+        if self.in_step {
+            self.out.write_all(b"</details>\n")?;
+        }
+        let elapsed = end.duration_since(self.start).unwrap_or_default();
+        write!(
+            self.out,
+            "</main>\n<footer>{} lines in {:.1}s",
+            self.lines,
+            elapsed.as_secs_f64()
+        )?;
+        if let Some(code) = exit_code {
+            write!(self.out, ", exit code {}", code)?;
+        }
+        self.out.write_all(b"</footer>\n</body></html>\n")?;
+        self.out.flush()
+    }
+}
+
+/// Background strength for a line's delta: nothing below 10ms, full at
+/// 10s and above, on a log scale in between
+fn shade(delta_secs: f64) -> f64 {
+    if delta_secs < 0.01 {
+        return 0.0;
+    }
+    ((delta_secs.log10() + 2.0) / 3.0).clamp(0.0, 1.0)
+}
+
+fn write_escaped(out: &mut impl Write, text: &str) -> io::Result<()> {
+    let mut start = 0;
+    for (i, b) in text.bytes().enumerate() {
+        let entity: &[u8] = match b {
+            b'&' => b"&amp;",
+            b'<' => b"&lt;",
+            b'>' => b"&gt;",
+            b'"' => b"&quot;",
+            _ => continue,
+        };
+        out.write_all(&text.as_bytes()[start..i])?;
+        out.write_all(entity)?;
+        start = i + 1;
+    }
+    out.write_all(&text.as_bytes()[start..])
+}
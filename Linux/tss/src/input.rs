@@ -0,0 +1,147 @@
+use crate::encoding::Decoding;
+use crate::lines::{Line, StampedLines};
+use crate::signals::{self, Interruptible};
+use crate::sink::Stream;
+use crate::{Config, Passthrough};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::SyncSender;
+use std::thread;
+
+#[derive(Clone)]
+pub enum Source {
+    Stdin,
+    Path(String),
+    Fd(RawFd),
+}
+
+/// A positional input or --input-fd, with its optional --label
+pub struct Input {
+    pub source: Source,
+    pub label: Option<String>,
+    // Stdout or Stderr for the pipes of a command; with --passthrough,
+    // the stderr pipe is echoed to stderr
+    pub stream: Option<Stream>,
+}
+
+/// What a reader thread hands to the main loop
+pub enum Event {
+    Line(usize, Line),
+    Error(usize, io::Error),
+}
+
+impl Input {
+    /// Name shown in front of its lines and recorded as the sink stream
+    pub fn name(&self) -> String {
+        if let Some(ref stream) = self.stream {
+            return stream.name().to_string();
+        }
+        if let Some(ref label) = self.label {
+            return label.clone();
+        }
+        match self.source {
+            Source::Stdin => "stdin".to_string(),
+            Source::Path(ref path) => path.clone(),
+            Source::Fd(fd) => format!("fd{}", fd),
+        }
+    }
+
+    /// Read this input on its own thread, stamping each line as it arrives
+    /// and sending it to the main loop as input `index`. With --passthrough,
+    /// the input is also copied to stdout, or stderr for the stderr of a
+    /// command.
+    pub fn spawn(&self, index: usize, config: &Config, wake: File, events: SyncSender<Event>) {
+        let source = self.source.clone();
+        let stderr = self.stream == Some(Stream::Stderr);
+        let (stamp_at, clock, encoding) = (config.stamp_at, config.clock, config.input_encoding);
+        let passthrough = config.passthrough.then_some(config.buffered);
+        thread::spawn(move || {
+            let file = match source.open() {
+                Ok(file) => Interruptible::new(file, wake),
+                Err(e) => {
+                    let _ = events.send(Event::Error(index, e));
+                    return;
+                }
+            };
+            // Passthrough copies the bytes as read, before any decoding
+            let file: Box<dyn Read> = match passthrough {
+                Some(buffered) if stderr => Box::new(Passthrough {
+                    inner: file,
+                    out: io::stderr(),
+                    buffered,
+                }),
+                Some(buffered) => Box::new(Passthrough {
+                    inner: file,
+                    out: io::stdout(),
+                    buffered,
+                }),
+                None => Box::new(file),
+            };
+            let reader: Box<dyn Read> = match encoding {
+                Some(encoding) => Box::new(Decoding::new(file, encoding)),
+                None => file,
+            };
+            send_lines(StampedLines::new(reader, stamp_at, clock), index, &events);
+        });
+    }
+}
+
+/// Hand every line to the main loop as input `index`, stopping at the
+/// first read error or once the main loop is gone
+pub fn send_lines(lines: impl Iterator<Item = io::Result<Line>>, index: usize, events: &SyncSender<Event>) {
+    for line_result in lines {
+        let event = match line_result {
+            Ok(line) => Event::Line(index, line),
+            Err(e) => Event::Error(index, e),
+        };
+        let failed = matches!(event, Event::Error(..));
+        if events.send(event).is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Run `command` with its stdout and stderr on pipes of their own, read as
+/// the inputs "stdout" and "stderr". Stdin is passed through unchanged.
+pub fn spawn_command(command: &[String]) -> io::Result<(Child, Vec<Input>)> {
+    let mut cmd = Command::new(&command[0]);
+    cmd.args(&command[1..])
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    signals::unblock_in_child(&mut cmd);
+    let mut child = cmd.spawn()?;
+    let stdout = child.stdout.take().map(IntoRawFd::into_raw_fd);
+    let stderr = child.stderr.take().map(IntoRawFd::into_raw_fd);
+    let inputs = [(stdout, Stream::Stdout), (stderr, Stream::Stderr)]
+        .into_iter()
+        .filter_map(|(fd, stream)| {
+            Some(Input {
+                source: Source::Fd(fd?),
+                label: None,
+                stream: Some(stream),
+            })
+        })
+        .collect();
+    Ok((child, inputs))
+}
+
+impl Source {
+    fn open(&self) -> io::Result<File> {
+        let fd = match *self {
+            // Opening a FIFO blocks until a writer shows up, which is why
+            // this happens on the reader thread
+            Source::Path(ref path) => return File::open(path),
+            Source::Stdin => libc::STDIN_FILENO,
+            Source::Fd(fd) => fd,
+        };
+        // SAFETY: fcntl only inspects the descriptor
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the descriptor is open and nothing else in tss reads it
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+}
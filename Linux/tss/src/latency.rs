@@ -0,0 +1,111 @@
+use crate::prefix::{skip_sgr, PrefixParser, NANOS_PER_DAY};
+use crate::Config;
+
+/// Recognises the prefix written by an earlier tss in the pipeline, so a
+/// later stage can append its own timestamp and the latency in between
+/// rather than nesting a new prefix in front of the old one.
+///
+/// A chain of stages produces `T0 T1 +L1 T2 +L2 line`, where each `Tn` is
+/// the time stage n saw the line and `Ln` the seconds since stage n-1.
+pub struct Restamper {
+    parser: PrefixParser,
+    separator: String,
+    latencies: Vec<i64>,
+}
+
+/// The prefix chain found on an incoming line
+pub struct Upstream<'a> {
+    // Everything before the original text, without the trailing separator
+    pub prefix: &'a str,
+    // Text of the line as the first stage saw it
+    pub text: &'a str,
+    position: i128,
+}
+
+impl Restamper {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            parser: PrefixParser::new(config),
+            separator: config.separator.clone(),
+            latencies: Vec::new(),
+        }
+    }
+
+    pub fn split<'a>(&mut self, line: &'a str) -> Option<Upstream<'a>> {
+        let (mut position, mut text) = self.parser.parse(line)?;
+
+        // Later stages each add "Tn +Ln"; the last Tn is what we measure from
+        // Peek, as text that merely starts with a timestamp is not a stage
+        while let Some((next, rest)) = self.parser.peek(text) {
+            let Some(rest) = strip_latency(rest).and_then(|r| skip_sgr(r).strip_prefix(self.separator.as_str())) else {
+                break;
+            };
+            position = next;
+            text = rest;
+        }
+        self.parser.commit(position);
+
+        let prefix_len = line.len() - text.len();
+        let prefix = line[..prefix_len]
+            .strip_suffix(self.separator.as_str())
+            .unwrap_or(&line[..prefix_len]);
+        Some(Upstream {
+            prefix,
+            text,
+            position,
+        })
+    }
+
+    /// Latency from the upstream stamp to our own `timestamp`, recorded for
+    /// the summary. Both go through the same parser, so local time and
+    /// time-of-day-only formats compare like for like.
+    pub fn latency(&mut self, upstream: &Upstream, timestamp: &str) -> Option<i64> {
+        let (ours, _) = self.parser.parse(timestamp)?;
+        let mut latency = ours - upstream.position;
+        // A wrap past midnight with time-of-day formats, in either direction
+        if latency > NANOS_PER_DAY / 2 {
+            latency -= NANOS_PER_DAY;
+        } else if latency < -NANOS_PER_DAY / 2 {
+            latency += NANOS_PER_DAY;
+        }
+        let latency = latency as i64;
+        self.latencies.push(latency);
+        Some(latency)
+    }
+
+    /// Latency percentiles over every restamped line, for stderr at exit
+    pub fn summary(&mut self) -> Option<String> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        self.latencies.sort_unstable();
+        let percentile = |p: usize| {
+            let rank = (p * self.latencies.len()).div_ceil(100).max(1);
+            format_seconds(self.latencies[rank - 1])
+        };
+        Some(format!(
+            "latency over {} lines: min {} p50 {} p90 {} p99 {} max {}",
+            self.latencies.len(),
+            format_seconds(self.latencies[0]),
+            percentile(50),
+            percentile(90),
+            percentile(99),
+            format_seconds(self.latencies[self.latencies.len() - 1]),
+        ))
+    }
+}
+
+/// Signed seconds with microsecond precision, e.g. "+0.001250"
+pub fn format_seconds(nanos: i64) -> String {
+    let sign = if nanos < 0 { '-' } else { '+' };
+    let micros = nanos.unsigned_abs() / 1_000;
+    format!("{}{}.{:06}", sign, micros / 1_000_000, micros % 1_000_000)
+}
+
+fn strip_latency(s: &str) -> Option<&str> {
+    let rest = s.strip_prefix(['+', '-'])?;
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(rest.len());
+    (end > 0).then(|| &rest[end..])
+}
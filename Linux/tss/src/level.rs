@@ -0,0 +1,57 @@
+/// Log level guessed from the text of a line
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Level {
+    pub const ALL: [Level; 6] = [
+        Level::Trace,
+        Level::Debug,
+        Level::Info,
+        Level::Warn,
+        Level::Error,
+        Level::Fatal,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+            Level::Fatal => "fatal",
+        }
+    }
+
+    /// Detect the level from the first level keyword in `line`. Keywords
+    /// must stand alone (`[ERROR]`, `level=warn`, `WARNING: `), so words such
+    /// as "information" or "errors: 0" in prose are not mistaken for levels.
+    /// Single-letter forms such as `E: ` are too ambiguous and not detected.
+    pub fn detect(line: &str) -> Option<Level> {
+        line.split(|c: char| !c.is_ascii_alphabetic())
+            .take(16)
+            .find_map(Self::from_word)
+    }
+
+    fn from_word(word: &str) -> Option<Level> {
+        let level = match word.len() {
+            4 if word.eq_ignore_ascii_case("info") => Level::Info,
+            4 if word.eq_ignore_ascii_case("warn") => Level::Warn,
+            5 if word.eq_ignore_ascii_case("trace") => Level::Trace,
+            5 if word.eq_ignore_ascii_case("debug") => Level::Debug,
+            5 if word.eq_ignore_ascii_case("error") => Level::Error,
+            5 if word.eq_ignore_ascii_case("fatal") => Level::Fatal,
+            7 if word.eq_ignore_ascii_case("warning") => Level::Warn,
+            8 if word.eq_ignore_ascii_case("critical") => Level::Fatal,
+            _ => return None,
+        };
+        Some(level)
+    }
+}
@@ -0,0 +1,135 @@
+use crate::clock::Clock;
+use crate::Stamp;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Which moment of a line's arrival its timestamp records
+#[derive(Clone, Copy, PartialEq)]
+pub enum StampAt {
+    Newline,
+    FirstByte,
+    // First byte, with the completion time shown as well
+    Both,
+}
+
+impl StampAt {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "newline" => Some(StampAt::Newline),
+            "first-byte" => Some(StampAt::FirstByte),
+            "both" => Some(StampAt::Both),
+            _ => None,
+        }
+    }
+}
+
+/// A line with the stamp it is shown with and, for `StampAt::Both`, the
+/// time its newline arrived
+pub struct Line {
+    pub text: String,
+    pub stamp: Stamp,
+    pub completed: Option<Stamp>,
+}
+
+/// Splits input into lines like `BufRead::lines`, but also knows when the
+/// first byte of each line arrived: the time of the read that returned it.
+pub struct StampedLines<R> {
+    reader: BufReader<Timed<R>>,
+    buf: Vec<u8>,
+    stamp_at: StampAt,
+    clock: Clock,
+}
+
+/// Remembers when the last read returned data
+struct Timed<R> {
+    inner: R,
+    clock: Clock,
+    last_read: Option<Stamp>,
+}
+
+impl<R: Read> Read for Timed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.last_read = Some(Stamp::now(self.clock));
+        }
+        Ok(n)
+    }
+}
+
+impl<R: Read> StampedLines<R> {
+    pub fn new(inner: R, stamp_at: StampAt, clock: Clock) -> Self {
+        let timed = Timed {
+            inner,
+            clock,
+            last_read: None,
+        };
+        Self {
+            reader: BufReader::with_capacity(128 * 1024, timed),
+            buf: Vec::new(),
+            stamp_at,
+            clock,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<Line>> {
+        self.buf.clear();
+        let mut first = None;
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if available.is_empty() {
+                break;
+            }
+            let newline = available.iter().position(|&b| b == b'\n');
+            let used = match newline {
+                Some(end) => {
+                    self.buf.extend_from_slice(&available[..end]);
+                    end + 1
+                }
+                None => {
+                    self.buf.extend_from_slice(available);
+                    available.len()
+                }
+            };
+            // The buffer only ever holds data from the most recent read
+            if first.is_none() {
+                first = self.reader.get_ref().last_read;
+            }
+            self.reader.consume(used);
+            if newline.is_some() {
+                break;
+            }
+        }
+        let Some(first) = first else {
+            return Ok(None);
+        };
+
+        if self.buf.last() == Some(&b'\r') {
+            self.buf.pop();
+        }
+        let text = String::from_utf8(std::mem::take(&mut self.buf))
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))?;
+        let done = Stamp::now(self.clock);
+        let (stamp, completed) = match self.stamp_at {
+            StampAt::Newline => (done, None),
+            StampAt::FirstByte => (first, None),
+            StampAt::Both => (first, Some(done)),
+        };
+        Ok(Some(Line {
+            text,
+            stamp,
+            completed,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for StampedLines<R> {
+    type Item = io::Result<Line>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_line().transpose()
+    }
+}
@@ -0,0 +1,56 @@
+use chrono::Locale;
+use std::env;
+
+/// Locale for day and month names, AM/PM and the %c, %x and %X layouts.
+/// Takes a POSIX locale name such as de_DE, fr_FR.UTF-8 or be_BY@latin;
+/// the character set is ignored and an unknown modifier is dropped.
+/// C and POSIX select the default English rendering, as None.
+pub fn from_name(name: &str) -> Result<Option<Locale>, String> {
+    if name == "C" || name == "POSIX" || name.starts_with("C.") {
+        return Ok(None);
+    }
+    let (base, modifier) = match name.split_once('@') {
+        Some((base, modifier)) => (base, Some(modifier)),
+        None => (name, None),
+    };
+    let base = base.split_once('.').map_or(base, |(base, _)| base);
+    if let Some(modifier) = modifier {
+        if let Ok(locale) = Locale::try_from(format!("{}@{}", base, modifier).as_str()) {
+            return Ok(Some(locale));
+        }
+    }
+    Locale::try_from(base)
+        .map(Some)
+        .map_err(|_| format!("Unknown locale: {}", name))
+}
+
+/// Locale the environment asks for with --locale env: the first of LC_ALL,
+/// LC_TIME and LANG that is set, like setlocale(3)
+pub fn from_env() -> Result<Option<Locale>, String> {
+    let Some((var, name)) = ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .filter_map(|&var| Some((var, env::var(var).ok()?)))
+        .find(|(_, value)| !value.is_empty())
+    else {
+        return Ok(None);
+    };
+    from_name(&name).map_err(|e| format!("{} (from {})", e, var))
+}
+
+/// Whether `format` renders differently per locale: names, AM/PM or the
+/// %c/%x/%X/%r layouts. Timestamps in such a format cannot be parsed back,
+/// as chrono only reads English names and layouts.
+pub fn is_localized(format: &str) -> bool {
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        // Padding flags and widths, as in %-d or %3f
+        let spec = chars.by_ref().find(|c| !matches!(c, '-' | '_' | '0'..='9' | '^' | '#' | ':' | '.'));
+        if matches!(spec, Some('a' | 'A' | 'b' | 'B' | 'h' | 'p' | 'P' | 'c' | 'x' | 'X' | 'r')) {
+            return true;
+        }
+    }
+    false
+}
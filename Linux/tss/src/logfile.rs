@@ -0,0 +1,470 @@
+use crate::longline::LongLines;
+use flate2::write::GzEncoder;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Whole lines are collected up to this size before a single write
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// Lines held back by --buffered are written out at least this often
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// A compressed frame is closed and written out at least this often, which
+// bounds what a crash can lose
+const FRAME_INTERVAL: Duration = Duration::from_secs(2);
+
+/// When written data is forced to stable storage
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fsync {
+    Never,
+    Line,
+    Every(Duration),
+}
+
+impl Fsync {
+    pub fn from_arg(value: &str) -> Option<Self> {
+        match value {
+            "never" => Some(Fsync::Never),
+            "line" => Some(Fsync::Line),
+            ms => ms
+                .strip_suffix("ms")
+                .unwrap_or(ms)
+                .parse::<u64>()
+                .ok()
+                .filter(|&ms| ms > 0)
+                .map(|ms| Fsync::Every(Duration::from_millis(ms))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Compression {
+    Plain,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Compression::Plain),
+            "gzip" | "gz" => Some(Compression::Gzip),
+            "zstd" | "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Compression implied by the file extension
+    pub fn for_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::Plain,
+        }
+    }
+}
+
+/// Which lines an output file receives
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileStream {
+    // --output: every line, as shown on the terminal
+    All,
+    Stdout,
+    Stderr,
+    // Every line, tagged with its sequence number and stream
+    Combined,
+}
+
+/// How escape sequences end up in an output file
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileColor {
+    // Timestamps uncolored, the input's own escape sequences kept
+    Default,
+    // Timestamps colored like on the terminal
+    Color,
+    // Escape sequences stripped from the input as well
+    Plain,
+}
+
+/// An output file as given on the command line
+pub struct FileSpec {
+    pub path: String,
+    pub stream: FileStream,
+    // None follows --force-overwrite
+    pub overwrite: Option<bool>,
+    pub color: FileColor,
+    // Also receives records hidden by --match/--exclude
+    pub unfiltered: bool,
+    // None follows --long-lines
+    pub long_lines: Option<LongLines>,
+}
+
+impl FileSpec {
+    /// Parse FILE[,OPTION...] where each option is append, overwrite, color,
+    /// plain, unfiltered, truncate, wrap or keep. Anything else after a comma
+    /// stays part of the file name.
+    pub fn parse(value: &str, stream: FileStream) -> Self {
+        let mut spec = FileSpec {
+            path: value.to_string(),
+            stream,
+            overwrite: None,
+            color: FileColor::Default,
+            unfiltered: false,
+            long_lines: None,
+        };
+        let (mut overwrite, mut color) = (None, None);
+        // Options are taken from the end; the last of each kind wins
+        while let Some((rest, option)) = spec.path.rsplit_once(',') {
+            match option {
+                "append" => overwrite = overwrite.or(Some(false)),
+                "overwrite" => overwrite = overwrite.or(Some(true)),
+                "color" => color = color.or(Some(FileColor::Color)),
+                "plain" => color = color.or(Some(FileColor::Plain)),
+                "unfiltered" => spec.unfiltered = true,
+                name @ ("truncate" | "wrap" | "keep") => {
+                    spec.long_lines = spec.long_lines.or(LongLines::from_name(name))
+                }
+                _ => break,
+            }
+            let len = rest.len();
+            spec.path.truncate(len);
+        }
+        spec.overwrite = overwrite;
+        spec.color = color.unwrap_or(FileColor::Default);
+        spec
+    }
+}
+
+/// An output file that is safe to share between several tss processes.
+/// Lines are only ever written whole, one write(2) per batch of complete
+/// lines on an O_APPEND descriptor, so concurrent appends never interleave
+/// partial lines. With `lock` each write also holds an exclusive flock for
+/// filesystems where O_APPEND alone is not enough.
+///
+/// Compressed files are written as a series of complete gzip members or
+/// zstd frames, each appended in one write, so the file can be read with
+/// zcat or zstdcat at any time while it grows.
+pub struct LogFile {
+    path: PathBuf,
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// State the background thread also touches, to write out held-back
+/// lines, close frames and fsync while no new lines arrive
+struct Shared {
+    file: File,
+    lock: bool,
+    fsync: Fsync,
+    compression: Compression,
+    // Whole lines not yet handed to the file or the open frame
+    buf: Vec<u8>,
+    frame: Option<Frame>,
+    // Set when data was written since the last timed fsync
+    dirty: bool,
+}
+
+struct Frame {
+    encoder: Encoder,
+    started: Instant,
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Zstd(zstd::Encoder<'static, Vec<u8>>),
+}
+
+impl LogFile {
+    /// Open `path` for appending, creating it and its parent directories.
+    /// With `overwrite` the file is truncated once up front; the descriptor
+    /// is still O_APPEND so other writers never clobber our lines.
+    pub fn open(
+        path: &str,
+        overwrite: bool,
+        lock: bool,
+        fsync: Fsync,
+        compression: Compression,
+    ) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        let file = open_append(&path)?;
+        if overwrite {
+            file.set_len(0)?;
+        }
+
+        let shared = Arc::new(Mutex::new(Shared {
+            file,
+            lock,
+            fsync,
+            compression,
+            buf: Vec::with_capacity(BUFFER_SIZE),
+            frame: None,
+            dirty: false,
+        }));
+
+        let sync_interval = match fsync {
+            Fsync::Every(interval) => Some(interval),
+            _ => None,
+        };
+        let mut tick = FLUSH_INTERVAL;
+        if compression != Compression::Plain {
+            tick = tick.min(FRAME_INTERVAL);
+        }
+        if let Some(interval) = sync_interval {
+            tick = tick.min(interval);
+        }
+        {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                let mut last_sync = Instant::now();
+                loop {
+                    thread::sleep(tick);
+                    let mut shared = shared.lock().unwrap();
+                    if let Err(e) = shared.flush() {
+                        eprintln!("tss: {}", e);
+                    }
+                    if shared.frame.as_ref().is_some_and(|f| f.started.elapsed() >= FRAME_INTERVAL) {
+                        match shared.end_frame() {
+                            Ok(()) => shared.dirty = true,
+                            Err(e) => eprintln!("tss: {}", e),
+                        }
+                    }
+                    if sync_interval.is_some_and(|interval| last_sync.elapsed() >= interval) && shared.dirty {
+                        let _ = shared.file.sync_data();
+                        shared.dirty = false;
+                        last_sync = Instant::now();
+                    }
+                }
+            });
+        }
+
+        Ok(Self { path, shared })
+    }
+
+    /// A callback that finishes the current file and continues in a fresh
+    /// one at the same path, after logrotate has moved the old one away. It
+    /// may run on any thread; on failure the old file stays in use.
+    pub fn reopener(&self) -> impl Fn() + Send + 'static {
+        let (path, shared) = (self.path.clone(), Arc::clone(&self.shared));
+        move || {
+            if let Err(e) = shared.lock().unwrap().reopen(&path) {
+                eprintln!("tss: reopen {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Queue one or more complete lines. Queued lines are written out when
+    /// the buffer fills, on `flush`, or by the background thread within
+    /// FLUSH_INTERVAL.
+    pub fn write_lines(&mut self, lines: &[u8]) -> io::Result<()> {
+        let mut shared = self.shared.lock().unwrap();
+        if !shared.buf.is_empty() && shared.buf.len() + lines.len() > BUFFER_SIZE {
+            shared.flush()?;
+        }
+        shared.buf.extend_from_slice(lines);
+        Ok(())
+    }
+
+    /// Hand the queued lines to the file, or to the open compressed frame
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.shared.lock().unwrap().flush()
+    }
+
+    /// Flush, close any open frame and force everything written so far to
+    /// disk, unless the policy is to never fsync
+    pub fn sync(&mut self) -> io::Result<()> {
+        let mut shared = self.shared.lock().unwrap();
+        shared.flush()?;
+        shared.end_frame()?;
+        if shared.fsync != Fsync::Never {
+            shared.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+impl Shared {
+    fn reopen(&mut self, path: &Path) -> io::Result<()> {
+        self.flush()?;
+        self.end_frame()?;
+        if self.fsync != Fsync::Never {
+            self.file.sync_data()?;
+        }
+        self.file = open_append(path)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let buf = std::mem::take(&mut self.buf);
+        let result = if self.compression == Compression::Plain {
+            self.append(&buf)
+        } else {
+            self.compress(&buf)
+        };
+        // Keep the allocation for the next lines
+        self.buf = buf;
+        self.buf.clear();
+        result?;
+
+        match self.fsync {
+            Fsync::Never => {}
+            Fsync::Line => self.file.sync_data()?,
+            Fsync::Every(_) => self.dirty = true,
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.lock {
+            flock(&self.file, libc::LOCK_EX)?;
+        }
+        let result = self.file.write_all(data);
+        if self.lock {
+            flock(&self.file, libc::LOCK_UN)?;
+        }
+        result
+    }
+
+    fn compress(&mut self, data: &[u8]) -> io::Result<()> {
+        let frame = match self.frame {
+            Some(ref mut frame) => frame,
+            None => {
+                let encoder = match self.compression {
+                    Compression::Gzip => {
+                        Encoder::Gzip(GzEncoder::new(Vec::new(), flate2::Compression::default()))
+                    }
+                    _ => Encoder::Zstd(zstd::Encoder::new(Vec::new(), zstd::DEFAULT_COMPRESSION_LEVEL)?),
+                };
+                self.frame.insert(Frame {
+                    encoder,
+                    started: Instant::now(),
+                })
+            }
+        };
+        match frame.encoder {
+            Encoder::Gzip(ref mut encoder) => encoder.write_all(data)?,
+            Encoder::Zstd(ref mut encoder) => encoder.write_all(data)?,
+        }
+        if frame.started.elapsed() >= FRAME_INTERVAL {
+            self.end_frame()?;
+        }
+        Ok(())
+    }
+
+    /// Close the open frame, if any, and append it to the file
+    fn end_frame(&mut self) -> io::Result<()> {
+        let Some(frame) = self.frame.take() else {
+            return Ok(());
+        };
+        let data = match frame.encoder {
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+        self.append(&data)
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn flock(file: &File, operation: libc::c_int) -> io::Result<()> {
+    loop {
+        // SAFETY: flock on a descriptor owned by `file`
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fsync_policies() {
+        assert!(Fsync::from_arg("never") == Some(Fsync::Never));
+        assert!(Fsync::from_arg("line") == Some(Fsync::Line));
+        assert!(Fsync::from_arg("250") == Some(Fsync::Every(Duration::from_millis(250))));
+        assert!(Fsync::from_arg("250ms") == Some(Fsync::Every(Duration::from_millis(250))));
+        assert!(Fsync::from_arg("0").is_none());
+        assert!(Fsync::from_arg("sometimes").is_none());
+    }
+
+    #[test]
+    fn compression_by_name_and_extension() {
+        assert!(Compression::from_name("gz") == Some(Compression::Gzip));
+        assert!(Compression::from_name("zstd") == Some(Compression::Zstd));
+        assert!(Compression::from_name("none") == Some(Compression::Plain));
+        assert!(Compression::from_name("lz4").is_none());
+        assert!(Compression::for_path("build.log.gz") == Compression::Gzip);
+        assert!(Compression::for_path("build.log.zst") == Compression::Zstd);
+        assert!(Compression::for_path("build.log") == Compression::Plain);
+    }
+
+    #[test]
+    fn plain_paths_take_the_defaults() {
+        let spec = FileSpec::parse("out.log", FileStream::All);
+        assert_eq!(spec.path, "out.log");
+        assert!(spec.overwrite.is_none());
+        assert!(spec.color == FileColor::Default);
+    }
+
+    #[test]
+    fn options_are_taken_from_the_end() {
+        let spec = FileSpec::parse("out.log,append,plain", FileStream::Stderr);
+        assert_eq!(spec.path, "out.log");
+        assert!(spec.stream == FileStream::Stderr);
+        assert_eq!(spec.overwrite, Some(false));
+        assert!(spec.color == FileColor::Plain);
+    }
+
+    #[test]
+    fn the_last_option_of_a_kind_wins() {
+        let spec = FileSpec::parse("out.log,append,color,overwrite,plain", FileStream::All);
+        assert_eq!(spec.overwrite, Some(true));
+        assert!(spec.color == FileColor::Plain);
+    }
+
+    #[test]
+    fn unknown_options_stay_in_the_name() {
+        let spec = FileSpec::parse("a,b.log,append", FileStream::All);
+        assert_eq!(spec.path, "a,b.log");
+        assert_eq!(spec.overwrite, Some(false));
+
+        let spec = FileSpec::parse("logs,color,v2", FileStream::All);
+        assert_eq!(spec.path, "logs,color,v2");
+        assert!(spec.color == FileColor::Default);
+    }
+
+    #[test]
+    fn unfiltered_is_an_option() {
+        assert!(!FileSpec::parse("out.log", FileStream::All).unfiltered);
+        let spec = FileSpec::parse("out.log,unfiltered,append", FileStream::All);
+        assert_eq!(spec.path, "out.log");
+        assert!(spec.unfiltered);
+        assert_eq!(spec.overwrite, Some(false));
+    }
+
+    #[test]
+    fn long_line_policies_are_options() {
+        assert!(FileSpec::parse("out.log", FileStream::All).long_lines.is_none());
+        let spec = FileSpec::parse("out.log,wrap,plain,keep", FileStream::All);
+        assert_eq!(spec.path, "out.log");
+        assert!(spec.long_lines == Some(LongLines::Keep));
+        assert!(spec.color == FileColor::Plain);
+    }
+}
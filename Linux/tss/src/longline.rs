@@ -0,0 +1,104 @@
+use crate::theme::RESET;
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// What happens to a line longer than --max-line-bytes
+#[derive(Clone, Copy, PartialEq)]
+pub enum LongLines {
+    // Cut it and note how many bytes were dropped
+    Truncate,
+    // Split it into several lines under the same prefix
+    Wrap,
+    // Write it whole
+    Keep,
+}
+
+impl LongLines {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "truncate" => Some(LongLines::Truncate),
+            "wrap" => Some(LongLines::Wrap),
+            "keep" => Some(LongLines::Keep),
+            _ => None,
+        }
+    }
+}
+
+/// The pieces `line` is written as when limited to `max` bytes of text.
+/// Cuts fall on character boundaries, so a piece is only longer than `max`
+/// when a single character is.
+pub fn split(line: &str, max: usize, policy: LongLines) -> Vec<Cow<'_, str>> {
+    if line.len() <= max || policy == LongLines::Keep {
+        return vec![Cow::Borrowed(line)];
+    }
+    if policy == LongLines::Truncate {
+        let end = floor_char_boundary(line, max);
+        let mut kept = line[..end].to_string();
+        // Colors the dropped part would have reset must not leak
+        if kept.contains('\x1b') {
+            kept.push_str(RESET);
+        }
+        let _ = write!(kept, "…(+{} bytes)", line.len() - end);
+        return vec![Cow::Owned(kept)];
+    }
+    let mut pieces = Vec::new();
+    let mut rest = line;
+    while rest.len() > max {
+        let end = match floor_char_boundary(rest, max) {
+            0 => rest.chars().next().map_or(rest.len(), char::len_utf8),
+            end => end,
+        };
+        pieces.push(Cow::Borrowed(&rest[..end]));
+        rest = &rest[end..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Cow::Borrowed(rest));
+    }
+    pieces
+}
+
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    let mut end = index.min(s.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_lines_and_keep_are_untouched() {
+        assert_eq!(split("short", 10, LongLines::Truncate), ["short"]);
+        assert_eq!(split("exactly10!", 10, LongLines::Wrap), ["exactly10!"]);
+        assert_eq!(split("a much longer line", 4, LongLines::Keep), ["a much longer line"]);
+    }
+
+    #[test]
+    fn truncate_notes_the_dropped_bytes() {
+        assert_eq!(split("abcdefghij", 4, LongLines::Truncate), ["abcd…(+6 bytes)"]);
+    }
+
+    #[test]
+    fn truncate_resets_cut_colors() {
+        let line = format!("\x1b[31mred text{}", RESET);
+        let kept = format!("\x1b[31mr{}…(+{} bytes)", RESET, line.len() - 6);
+        assert_eq!(split(&line, 6, LongLines::Truncate), [kept]);
+    }
+
+    #[test]
+    fn wrap_splits_into_pieces() {
+        assert_eq!(split("abcdefghij", 4, LongLines::Wrap), ["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn cuts_fall_on_character_boundaries() {
+        // "é" is two bytes, so a cut after three bytes keeps one of them
+        assert_eq!(split("aéé", 2, LongLines::Wrap), ["a", "é", "é"]);
+        assert_eq!(split("ééé", 3, LongLines::Truncate), ["é…(+4 bytes)"]);
+        // A character longer than the limit still makes progress
+        assert_eq!(split("€€", 2, LongLines::Wrap), ["€", "€"]);
+    }
+}
@@ -1,9 +1,57 @@
+mod banner;
+mod clock;
+mod dedupe;
+mod encoding;
+mod filter;
+mod html;
+mod input;
+mod latency;
+mod level;
+mod lines;
+mod locale;
+mod logfile;
+mod longline;
+mod metrics;
+mod net;
+mod otlp;
+mod prefix;
+mod pty;
+mod replay;
+mod signals;
+mod sink;
+mod sqlite;
+mod status;
+mod theme;
+
+use std::borrow::Cow;
 use std::env;
-use std::io::{self, BufRead, BufReader, Write, BufWriter};
+use std::io::{self, Write, BufWriter};
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH, Instant};
-use std::fs::{create_dir_all, OpenOptions};
-use chrono::{DateTime, Local, Utc, TimeZone, Timelike, Datelike};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH, Instant};
+use regex::Regex;
+use banner::{Banner, BannerTarget};
+use clock::Clock;
+use dedupe::{Deduper, Repeat};
+use encoding::{Decoding, OutputEncoding};
+use filter::{Filter, Highlight};
+use html::HtmlSink;
+use input::{Event, Input, Source};
+use lines::{StampAt, StampedLines};
+use logfile::{Compression, FileColor, FileSpec, FileStream, Fsync, LogFile};
+use longline::LongLines;
+use metrics::MetricsSink;
+use net::{NetSink, Overflow};
+use replay::ReplayOptions;
+use signals::Interruptible;
+use otlp::{OtlpConfig, OtlpSink, Protocol};
+use latency::{format_seconds, Restamper};
+use sink::{Record, RunInfo, Sink, Stream, UpstreamStamp};
+use sqlite::SqliteSink;
+use status::Status;
+use theme::{ColorMode, Theme};
+use chrono::{DateTime, Local, Locale, Utc, TimeZone, Timelike, Datelike};
 
 struct Config {
     format: String,
@@ -18,15 +66,57 @@ struct Config {
     delta: bool,
     prefix_only: bool,
     color: bool,
+    // Colors for stdout and files written with ,color; None when unused
+    theme: Option<Theme>,
     buffered: bool,
     timezone: Option<String>,
-    output_file: Option<String>,
+    // None renders names and %c/%x/%X in English
+    locale: Option<Locale>,
+    output_files: Vec<FileSpec>,
     force_overwrite: bool,
+    banner: Option<BannerTarget>,
+    lock: bool,
+    fsync: Fsync,
+    input_encoding: Option<&'static encoding_rs::Encoding>,
+    output_encoding: Option<OutputEncoding>,
+    compress: Option<Compression>,
+    pty: bool,
+    command: Vec<String>,
+    inputs: Vec<Input>,
+    json: bool,
+    filter: Filter,
+    highlights: Vec<Highlight>,
+    max_line_bytes: Option<usize>,
+    long_lines: LongLines,
+    multiline: Option<Regex>,
+    continuation: Continuation,
+    dedupe: bool,
+    dedupe_ignore_digits: bool,
+    dedupe_ignore: Option<Regex>,
+    passthrough: bool,
+    sqlite: Option<String>,
+    html: Option<String>,
+    html_steps: Option<Regex>,
+    otlp: Option<String>,
+    otlp_protocol: Protocol,
+    otlp_service: String,
+    otlp_attributes: Vec<(String, String)>,
+    run_id: Option<String>,
+    metrics_file: Option<String>,
+    sinks: Vec<String>,
+    sink_overflow: Overflow,
+    metrics_interval: Duration,
+    replay: Option<ReplayOptions>,
+    stamp_again: bool,
+    stamp_at: StampAt,
+    status: bool,
+    clock: Clock,
+    dmesg: bool,
 }
 
-impl Config {
-    fn parse_args() -> Result<Self, Box<dyn std::error::Error>> {
-        let mut config = Config {
+impl Default for Config {
+    fn default() -> Self {
+        Config {
             format: "%Y-%m-%d %H:%M:%S".to_string(),
             separator: " ".to_string(),
             relative: false,
@@ -39,16 +129,76 @@ impl Config {
             delta: false,
             prefix_only: false,
             color: false,
+            theme: None,
             buffered: false, // Default to unbuffered for real-time output
             timezone: None,
-            output_file: None,
+            locale: None,
+            output_files: Vec::new(),
             force_overwrite: false,
-        };
+            banner: None,
+            lock: false,
+            fsync: Fsync::Never,
+            input_encoding: None,
+            output_encoding: None,
+            compress: None,
+            pty: false,
+            command: Vec::new(),
+            inputs: Vec::new(),
+            json: false,
+            filter: Filter::default(),
+            highlights: Vec::new(),
+            max_line_bytes: None,
+            long_lines: LongLines::Truncate,
+            multiline: None,
+            continuation: Continuation::Pad,
+            dedupe: false,
+            dedupe_ignore_digits: false,
+            dedupe_ignore: None,
+            passthrough: false,
+            sqlite: None,
+            html: None,
+            html_steps: None,
+            otlp: None,
+            otlp_protocol: Protocol::Protobuf,
+            otlp_service: "tss".to_string(),
+            otlp_attributes: Vec::new(),
+            run_id: None,
+            metrics_file: None,
+            sinks: Vec::new(),
+            sink_overflow: Overflow::Drop,
+            metrics_interval: Duration::from_secs(10),
+            replay: None,
+            stamp_again: false,
+            stamp_at: StampAt::Newline,
+            status: false,
+            clock: Clock::Realtime,
+            dmesg: false,
+        }
+    }
+}
+
+impl Config {
+    fn parse_args() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut config = Config::default();
         
         let args: Vec<String> = env::args().collect();
         let program_name = Self::get_program_name(&args[0]);
         
+        // Set by --label and taken by the next input
+        let mut label = None;
+        let mut color = ColorMode::Never;
+        let mut locale_name = None;
         let mut i = 1;
+        if args.get(1).is_some_and(|arg| arg == "replay") {
+            config.replay = Some(ReplayOptions {
+                input: String::new(),
+                speed: 1.0,
+                max_gap: None,
+                seek: Duration::ZERO,
+                keep_timestamps: false,
+            });
+            i += 1;
+        }
         while i < args.len() {
             match args[i].as_str() {
                 "-h" | "--help" => {
@@ -67,9 +217,374 @@ impl Config {
                 "--nanoseconds" => config.nanoseconds = true,
                 "--delta" => config.delta = true,
                 "--prefix-only" => config.prefix_only = true,
-                "--color" => config.color = true,
+                "--color" => color = ColorMode::Always,
+                arg if arg.starts_with("--color=") => {
+                    color = match ColorMode::from_name(&arg["--color=".len()..]) {
+                        Some(mode) => mode,
+                        None => {
+                            eprintln!("Error: Invalid --color value: {} (expected auto, always or never)", &arg["--color=".len()..]);
+                            std::process::exit(1);
+                        }
+                    };
+                }
+                "--banner" => config.banner = Some(BannerTarget::Files),
+                arg if arg.starts_with("--banner=") => {
+                    config.banner = match BannerTarget::from_name(&arg["--banner=".len()..]) {
+                        Some(target) => Some(target),
+                        None => {
+                            eprintln!("Error: Invalid --banner value: {} (expected files or all)", &arg["--banner=".len()..]);
+                            std::process::exit(1);
+                        }
+                    };
+                }
                 "--buffered" => config.buffered = true,
                 "--force-overwrite" => config.force_overwrite = true,
+                "--lock" => config.lock = true,
+                "--compress" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --compress requires a value");
+                        std::process::exit(1);
+                    }
+                    config.compress = match Compression::from_name(&args[i]) {
+                        Some(compression) => Some(compression),
+                        None => {
+                            eprintln!("Error: Invalid --compress value: {} (expected gzip, zstd or none)", args[i]);
+                            std::process::exit(1);
+                        }
+                    };
+                }
+                "--fsync" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --fsync requires a value");
+                        std::process::exit(1);
+                    }
+                    config.fsync = match Fsync::from_arg(&args[i]) {
+                        Some(fsync) => fsync,
+                        None => {
+                            eprintln!("Error: Invalid --fsync value: {} (expected never, line or a positive number of milliseconds)", args[i]);
+                            std::process::exit(1);
+                        }
+                    };
+                }
+                "--input-encoding" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --input-encoding requires a value");
+                        std::process::exit(1);
+                    }
+                    match encoding::for_label(&args[i]) {
+                        Some(encoding) => config.input_encoding = Some(encoding),
+                        None => {
+                            eprintln!("Error: Unknown --input-encoding: {}", args[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--output-encoding" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --output-encoding requires a value");
+                        std::process::exit(1);
+                    }
+                    match OutputEncoding::for_label(&args[i]) {
+                        Some(encoding) => config.output_encoding = Some(encoding),
+                        None => {
+                            eprintln!("Error: Unknown --output-encoding: {}", args[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--pty" => config.pty = true,
+                "--passthrough" => config.passthrough = true,
+                "--stamp-again" => config.stamp_again = true,
+                "--status" => config.status = true,
+                "--dmesg" => config.dmesg = true,
+                "--stamp-at" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --stamp-at requires a value");
+                        std::process::exit(1);
+                    }
+                    config.stamp_at = match StampAt::from_name(&args[i]) {
+                        Some(stamp_at) => stamp_at,
+                        None => {
+                            eprintln!("Error: Invalid --stamp-at value: {} (expected newline, first-byte or both)", args[i]);
+                            std::process::exit(1);
+                        }
+                    };
+                }
+                "--clock" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --clock requires a value");
+                        std::process::exit(1);
+                    }
+                    config.clock = match Clock::from_name(&args[i]) {
+                        Some(clock) => clock,
+                        None => {
+                            eprintln!("Error: Invalid --clock value: {} (expected realtime, monotonic, monotonic-raw, boottime or tai)", args[i]);
+                            std::process::exit(1);
+                        }
+                    };
+                }
+                "--json" => config.json = true,
+                "--html" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --html requires a value");
+                        std::process::exit(1);
+                    }
+                    config.html = Some(args[i].clone());
+                }
+                "--html-steps" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --html-steps requires a value");
+                        std::process::exit(1);
+                    }
+                    match Regex::new(&args[i]) {
+                        Ok(re) => config.html_steps = Some(re),
+                        Err(e) => {
+                            eprintln!("Error: Invalid --html-steps pattern: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--match" | "--exclude" => {
+                    let option = args[i].clone();
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: {} requires a value", option);
+                        std::process::exit(1);
+                    }
+                    match Regex::new(&args[i]) {
+                        Ok(re) if option == "--match" => config.filter.matches.push(re),
+                        Ok(re) => config.filter.excludes.push(re),
+                        Err(e) => {
+                            eprintln!("Error: Invalid {} pattern: {}", option, e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--highlight" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --highlight requires a value");
+                        std::process::exit(1);
+                    }
+                    let Some((pattern, color)) = args[i].rsplit_once('=') else {
+                        eprintln!("Error: --highlight expects REGEX=COLOR, got: {}", args[i]);
+                        std::process::exit(1);
+                    };
+                    let regex = match Regex::new(pattern) {
+                        Ok(re) => re,
+                        Err(e) => {
+                            eprintln!("Error: Invalid --highlight pattern: {}", e);
+                            std::process::exit(1);
+                        }
+                    };
+                    match theme::color(color) {
+                        Ok(color) => config.highlights.push(Highlight { regex, color }),
+                        Err(e) => {
+                            eprintln!("Error: Invalid --highlight color: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--multiline" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --multiline requires a value");
+                        std::process::exit(1);
+                    }
+                    match Regex::new(&args[i]) {
+                        Ok(re) => config.multiline = Some(re),
+                        Err(e) => {
+                            eprintln!("Error: Invalid --multiline pattern: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--dedupe" => config.dedupe = true,
+                "--dedupe-ignore-digits" => {
+                    config.dedupe = true;
+                    config.dedupe_ignore_digits = true;
+                }
+                "--dedupe-ignore" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --dedupe-ignore requires a value");
+                        std::process::exit(1);
+                    }
+                    match Regex::new(&args[i]) {
+                        Ok(re) => {
+                            config.dedupe = true;
+                            config.dedupe_ignore = Some(re);
+                        }
+                        Err(e) => {
+                            eprintln!("Error: Invalid --dedupe-ignore pattern: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--sqlite" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --sqlite requires a value");
+                        std::process::exit(1);
+                    }
+                    config.sqlite = Some(args[i].clone());
+                }
+                "--otlp" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --otlp requires a value");
+                        std::process::exit(1);
+                    }
+                    config.otlp = Some(args[i].clone());
+                }
+                "--sink" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --sink requires a value");
+                        std::process::exit(1);
+                    }
+                    config.sinks.push(args[i].clone());
+                }
+                "--sink-overflow" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --sink-overflow requires a value");
+                        std::process::exit(1);
+                    }
+                    match Overflow::from_name(&args[i]) {
+                        Some(overflow) => config.sink_overflow = overflow,
+                        None => {
+                            eprintln!("Error: Invalid --sink-overflow value: {} (expected drop or block)", args[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--otlp-protocol" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --otlp-protocol requires a value");
+                        std::process::exit(1);
+                    }
+                    config.otlp_protocol = match args[i].as_str() {
+                        "json" => Protocol::Json,
+                        "protobuf" => Protocol::Protobuf,
+                        other => {
+                            eprintln!("Error: Invalid --otlp-protocol value: {} (expected json or protobuf)", other);
+                            std::process::exit(1);
+                        }
+                    };
+                }
+                "--otlp-service" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --otlp-service requires a value");
+                        std::process::exit(1);
+                    }
+                    config.otlp_service = args[i].clone();
+                }
+                "--otlp-attr" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --otlp-attr requires a value");
+                        std::process::exit(1);
+                    }
+                    match args[i].split_once('=') {
+                        Some((key, value)) if !key.is_empty() => {
+                            config.otlp_attributes.push((key.to_string(), value.to_string()));
+                        }
+                        _ => {
+                            eprintln!("Error: --otlp-attr expects KEY=VALUE, got: {}", args[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--run-id" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --run-id requires a value");
+                        std::process::exit(1);
+                    }
+                    config.run_id = Some(args[i].clone());
+                }
+                "--metrics-file" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --metrics-file requires a value");
+                        std::process::exit(1);
+                    }
+                    config.metrics_file = Some(args[i].clone());
+                }
+                "--metrics-interval" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --metrics-interval requires a value");
+                        std::process::exit(1);
+                    }
+                    match args[i].parse::<f64>() {
+                        Ok(secs) if secs > 0.0 && secs.is_finite() => {
+                            config.metrics_interval = Duration::from_secs_f64(secs);
+                        }
+                        _ => {
+                            eprintln!("Error: Invalid --metrics-interval value: {}", args[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--max-line-bytes" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --max-line-bytes requires a value");
+                        std::process::exit(1);
+                    }
+                    match args[i].parse::<usize>() {
+                        Ok(max) if max > 0 => config.max_line_bytes = Some(max),
+                        _ => {
+                            eprintln!("Error: Invalid --max-line-bytes value: {}", args[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--long-lines" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --long-lines requires a value");
+                        std::process::exit(1);
+                    }
+                    match LongLines::from_name(&args[i]) {
+                        Some(policy) => config.long_lines = policy,
+                        None => {
+                            eprintln!("Error: Invalid --long-lines value: {} (expected truncate, wrap or keep)", args[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--continuation" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --continuation requires a value");
+                        std::process::exit(1);
+                    }
+                    config.continuation = match args[i].as_str() {
+                        "pad" => Continuation::Pad,
+                        "none" => Continuation::None,
+                        other => {
+                            eprintln!("Error: Invalid --continuation value: {} (expected pad or none)", other);
+                            std::process::exit(1);
+                        }
+                    };
+                }
+                "--" => {
+                    config.command = args[i + 1..].to_vec();
+                    break;
+                }
                 "-s" | "--separator" => {
                     i += 1;
                     if i >= args.len() {
@@ -86,6 +601,14 @@ impl Config {
                     }
                     config.format = args[i].clone();
                 }
+                "--locale" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --locale requires a value");
+                        std::process::exit(1);
+                    }
+                    locale_name = Some(args[i].clone());
+                }
                 "--timezone" => {
                     i += 1;
                     if i >= args.len() {
@@ -94,39 +617,271 @@ impl Config {
                     }
                     config.timezone = Some(args[i].clone());
                 }
-                "-o" | "--output" => {
+                "-o" | "--output" | "--stdout-file" | "--stderr-file" | "--combined-file" => {
+                    let (option, stream) = match args[i].as_str() {
+                        "--stdout-file" => ("--stdout-file", FileStream::Stdout),
+                        "--stderr-file" => ("--stderr-file", FileStream::Stderr),
+                        "--combined-file" => ("--combined-file", FileStream::Combined),
+                        _ => ("--output", FileStream::All),
+                    };
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: {} requires a value", option);
+                        std::process::exit(1);
+                    }
+                    let spec = FileSpec::parse(&args[i], stream);
+                    if spec.path.is_empty() {
+                        eprintln!("Error: {} requires a file name before its options", option);
+                        std::process::exit(1);
+                    }
+                    config.output_files.push(spec);
+                }
+                "--speed" if config.replay.is_some() => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --speed requires a value");
+                        std::process::exit(1);
+                    }
+                    match args[i].parse::<f64>() {
+                        Ok(speed) if speed > 0.0 && speed.is_finite() => {
+                            if let Some(ref mut replay) = config.replay {
+                                replay.speed = speed;
+                            }
+                        }
+                        _ => {
+                            eprintln!("Error: Invalid --speed value: {}", args[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--max-gap" if config.replay.is_some() => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --max-gap requires a value");
+                        std::process::exit(1);
+                    }
+                    match Self::parse_offset(&args[i]) {
+                        Some(gap) => {
+                            if let Some(ref mut replay) = config.replay {
+                                replay.max_gap = Some(gap);
+                            }
+                        }
+                        None => {
+                            eprintln!("Error: Invalid --max-gap value: {}", args[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--seek" if config.replay.is_some() => {
                     i += 1;
                     if i >= args.len() {
-                        eprintln!("Error: --output requires a value");
+                        eprintln!("Error: --seek requires a value");
                         std::process::exit(1);
                     }
-                    config.output_file = Some(args[i].clone());
+                    match Self::parse_offset(&args[i]) {
+                        Some(seek) => {
+                            if let Some(ref mut replay) = config.replay {
+                                replay.seek = seek;
+                            }
+                        }
+                        None => {
+                            eprintln!("Error: Invalid --seek value: {}", args[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--keep-timestamps" if config.replay.is_some() => {
+                    if let Some(ref mut replay) = config.replay {
+                        replay.keep_timestamps = true;
+                    }
+                }
+                arg if config.replay.as_ref().is_some_and(|r| r.input.is_empty())
+                    && (arg == "-" || !arg.starts_with('-')) =>
+                {
+                    if let Some(ref mut replay) = config.replay {
+                        replay.input = arg.to_string();
+                    }
+                }
+                "--label" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --label requires a value");
+                        std::process::exit(1);
+                    }
+                    label = Some(args[i].clone());
+                }
+                "--input-fd" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("Error: --input-fd requires a value");
+                        std::process::exit(1);
+                    }
+                    match args[i].parse::<i32>() {
+                        Ok(fd) if fd >= 0 => config.inputs.push(Input {
+                            source: Source::Fd(fd),
+                            label: label.take(),
+                            stream: None,
+                        }),
+                        _ => {
+                            eprintln!("Error: Invalid --input-fd value: {}", args[i]);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                arg if config.replay.is_none() && (arg == "-" || !arg.starts_with('-')) => {
+                    let source = if arg == "-" { Source::Stdin } else { Source::Path(arg.to_string()) };
+                    config.inputs.push(Input {
+                        source,
+                        label: label.take(),
+                        stream: None,
+                    });
                 }
                 _ => {
                     eprintln!("Unknown argument: {}", args[i]);
                     std::process::exit(1);
                 }
             }
-            i += 1;
+            i += 1;
+        }
+        
+        // Validation
+        if config.microseconds && config.nanoseconds {
+            eprintln!("Error: Cannot use both --microseconds and --nanoseconds");
+            std::process::exit(1);
+        }
+        if config.relative && config.since_epoch {
+            eprintln!("Error: Cannot use both --relative and --epoch");
+            std::process::exit(1);
+        }
+        if config.relative && config.delta {
+            eprintln!("Error: Cannot use both --relative and --delta");
+            std::process::exit(1);
+        }
+        if config.replay.as_ref().is_some_and(|r| r.input.is_empty()) {
+            eprintln!("Error: replay requires a FILE (or - for stdin)");
+            std::process::exit(1);
+        }
+        if config.monotonic && config.clock != Clock::Realtime {
+            eprintln!("Error: Cannot use both --monotonic and --clock");
+            std::process::exit(1);
+        }
+        if !(config.clock.is_wall() || config.relative || config.delta || config.since_epoch) {
+            eprintln!("Error: --clock without a calendar date requires --relative, --delta or --epoch");
+            std::process::exit(1);
+        }
+        if config.dmesg && (config.monotonic || config.clock != Clock::Realtime) {
+            eprintln!("Error: --dmesg converts to wall-clock time and cannot be used with --monotonic or --clock");
+            std::process::exit(1);
+        }
+        if config.stamp_again && (config.relative || config.delta) {
+            eprintln!("Error: --stamp-again requires absolute timestamps (not --relative or --delta)");
+            std::process::exit(1);
+        }
+        if config.stamp_at == StampAt::Both && config.delta {
+            eprintln!("Error: --stamp-at both cannot be used with --delta");
+            std::process::exit(1);
+        }
+        if config.json && config.prefix_only {
+            eprintln!("Error: Cannot use both --json and --prefix-only");
+            std::process::exit(1);
+        }
+        if config.max_line_bytes.is_some() && (config.json || config.prefix_only) {
+            eprintln!("Error: --max-line-bytes cannot be used with --json or --prefix-only");
+            std::process::exit(1);
+        }
+        if config.dedupe && config.multiline.is_some() {
+            eprintln!("Error: Cannot use both --dedupe and --multiline");
+            std::process::exit(1);
+        }
+        if config.passthrough && config.output_files.is_empty() && config.sqlite.is_none() && config.otlp.is_none() && config.html.is_none() && config.sinks.is_empty() {
+            eprintln!("Error: --passthrough requires an output file, --sqlite, --otlp, --html or --sink");
+            std::process::exit(1);
+        }
+        if (config.lock || config.fsync != Fsync::Never || config.compress.is_some()) && config.output_files.is_empty() {
+            eprintln!("Error: --lock, --fsync and --compress require an output file");
+            std::process::exit(1);
+        }
+        if config.output_encoding.is_some() && config.output_files.is_empty() {
+            eprintln!("Error: --output-encoding requires an output file");
+            std::process::exit(1);
+        }
+        if config.banner == Some(BannerTarget::Files) && config.output_files.is_empty() {
+            eprintln!("Error: --banner requires an output file (use --banner=all for stdout)");
+            std::process::exit(1);
+        }
+        let split_streams = config
+            .output_files
+            .iter()
+            .any(|spec| matches!(spec.stream, FileStream::Stdout | FileStream::Stderr));
+        if split_streams && !config.runs_piped() {
+            eprintln!("Error: --stdout-file and --stderr-file require a command run without --pty");
+            std::process::exit(1);
+        }
+        let locale = match locale_name.as_deref() {
+            None => Ok(None),
+            Some("env") => locale::from_env(),
+            Some(name) => locale::from_name(name),
+        };
+        config.locale = match locale {
+            Ok(locale) => locale,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        if config.locale.is_some()
+            && (config.replay.is_some() || config.stamp_again)
+            && locale::is_localized(&config.format)
+        {
+            eprintln!("Error: replay and --stamp-again cannot parse --locale names; leave %a %b %p %c %x %X out of --format");
+            std::process::exit(1);
+        }
+        config.color = color.enabled();
+        if config.color || config.output_files.iter().any(|spec| spec.color == FileColor::Color) {
+            match Theme::load() {
+                Ok(theme) => config.theme = Some(theme),
+                Err(e) => {
+                    eprintln!("Error: Invalid theme: {}", e);
+                    std::process::exit(1);
+                }
+            }
         }
-        
-        // Validation
-        if config.microseconds && config.nanoseconds {
-            eprintln!("Error: Cannot use both --microseconds and --nanoseconds");
+        if label.is_some() {
+            eprintln!("Error: --label must be followed by the input it names");
             std::process::exit(1);
         }
-        if config.relative && config.since_epoch {
-            eprintln!("Error: Cannot use both --relative and --epoch");
+        if !config.command.is_empty() && !config.inputs.is_empty() {
+            eprintln!("Error: Cannot use both a command and input files");
             std::process::exit(1);
         }
-        if config.relative && config.delta {
-            eprintln!("Error: Cannot use both --relative and --delta");
+        if config.pty && config.command.is_empty() {
+            eprintln!("Error: --pty requires a command after --");
             std::process::exit(1);
         }
         
         Ok(config)
     }
     
+    /// Parse a duration given as seconds or [[HH:]MM:]SS, with optional
+    /// fractional seconds
+    fn parse_offset(value: &str) -> Option<Duration> {
+        let mut secs = 0.0;
+        for part in value.split(':') {
+            secs = secs * 60.0 + part.parse::<f64>().ok().filter(|v| *v >= 0.0)?;
+        }
+        secs.is_finite().then(|| Duration::from_secs_f64(secs))
+    }
+    
+    /// Whether lines carry the name of the input they came from
+    fn show_labels(&self) -> bool {
+        self.inputs.len() > 1 || self.inputs.iter().any(|input| input.label.is_some())
+    }
+    
+    /// Whether a command runs with its stdout and stderr on separate pipes
+    fn runs_piped(&self) -> bool {
+        !self.command.is_empty() && !self.pty
+    }
+    
     fn get_program_name(argv0: &str) -> String {
         Path::new(argv0)
             .file_name()
@@ -139,52 +894,151 @@ impl Config {
         println!(
             "{} - timestamp each line of input stream
 
-Usage: {} [OPTIONS]
+Usage: {} [OPTIONS] [INPUT...]
+       {} [OPTIONS] [--pty] -- COMMAND [ARGS...]
+       {} replay [OPTIONS] FILE
 
 Options:
+  --banner[=WHERE]         Add start/end lines with run metadata to output files (all: stdout too)
   --buffered               Use buffered output (default is unbuffered)
-  --color                  Colorize timestamps
+  --clock CLOCK            Clock source: realtime, monotonic, monotonic-raw, boottime, tai
+                           (default: realtime; non-calendar clocks need -r, --delta or -e)
+  --color[=WHEN]           Colorize timestamps: auto, always, never (default: never; bare --color is always)
+  --combined-file FILE     Write all lines to FILE, each tagged with its sequence number and stream
+  --compress ALGO          Compress output files: gzip, zstd, none (default: from .gz/.zst extension)
+  --continuation MODE      Prefix for --multiline continuation lines: pad, none (default: pad)
+  --dedupe                 Collapse consecutive identical lines into a repeat count
+  --dedupe-ignore REGEX    Ignore text matching REGEX when comparing lines (implies --dedupe)
+  --dedupe-ignore-digits   Ignore digits when comparing lines (implies --dedupe)
   --delta                  Show time delta between lines 
+  --dmesg                  Convert dmesg \"[seconds since boot]\" prefixes to wall-clock time
   -e, --epoch              Show seconds since Unix epoch
+  --exclude REGEX          Hide lines matching REGEX (repeatable)
   -f, --format FORMAT      Date format (default: %Y-%m-%d %H:%M:%S)
-  --force-overwrite        Overwrite output file instead of appending
+  --force-overwrite        Overwrite output files instead of appending
+  --fsync POLICY           Sync output files to disk: never, line, or every N ms (default: never)
+  --highlight REGEX=COLOR  Color text matching REGEX when colors are on (repeatable)
   -i, --iso                Use ISO 8601 format (2025-07-03T14:30:45.123+05:45)
   -h, --help               Show this help
+  --html FILE              Write a self-contained HTML report with steps, search and a timeline
+  --html-steps REGEX       Lines matching REGEX start a collapsible step in --html
+  --input-encoding ENC     Decode input from ENC, e.g. latin1, windows-1252, utf-16le (a BOM wins)
+  --input-fd FD            Read input from file descriptor FD (e.g. 3 with 3<file)
+  --json                   Write one JSON object per record
+  --label NAME             Name the next input; lines show [NAME] after the timestamp
+  --locale NAME            Day and month names, AM/PM and %c/%x/%X in locale NAME, e.g. de_DE
+                           or env for LC_ALL, LC_TIME or LANG (default: C, English)
+  --lock                   Hold an exclusive flock on an output file for each write
+  --long-lines POLICY      Lines over --max-line-bytes: truncate, wrap, keep (default: truncate)
+  --match REGEX            Only show lines matching REGEX (repeatable)
+  --max-line-bytes N       Limit the text of a line to N bytes, see --long-lines
+  --metrics-file FILE      Keep Prometheus textfile metrics in FILE
+  --metrics-interval SECS  How often --metrics-file is rewritten (default: 10)
   --microseconds           Show microseconds precision
   -m, --monotonic          Use monotonic clock for relative timestamps
+  --multiline REGEX        Attach lines matching REGEX to the previous record
   --nanoseconds            Show nanoseconds precision
   -o, --output FILE        Write timestamped output to file (appends by default)
+  --output-encoding ENC    Write output files in ENC, e.g. windows-1252, utf-16le (default: utf-8)
+  --otlp URL               Export lines as OTLP logs (e.g. http://localhost:4318/v1/logs)
+  --otlp-attr KEY=VALUE    Add an OTLP resource attribute (repeatable)
+  --otlp-protocol PROTO    OTLP encoding: protobuf, json (default: protobuf)
+  --otlp-service NAME      OTLP service.name resource attribute (default: tss)
+  --passthrough            Copy input to stdout unmodified, timestamp only the file sinks
   --prefix-only            Only show timestamp prefix (no input lines)
+  --pty                    Run COMMAND under a pseudo-terminal (default: stdout and stderr on pipes)
   -r, --relative           Show relative timestamps from start
+  --run-id ID              Identifier for this run in sinks (default: generated)
   -s, --separator SEP      Separator between timestamp and line (default: \" \")
+  --sink URL               Send each line, as text or with --json as JSON, to tcp://HOST:PORT,
+                           udp://HOST:PORT or unix:///PATH without holding up stdout (repeatable)
+  --sink-overflow MODE     When a --sink falls behind: drop lines or block until it catches up
+                           (default: drop)
+  --sqlite FILE            Insert every line into an SQLite database
+  --stamp-again            Extend an upstream tss prefix with a second timestamp and latency
+  --stamp-at WHEN          Stamp lines at their newline (default), first-byte, or both
+  --status                 Show a live progress line on stderr while stdout goes to a file or pipe
+  --stderr-file FILE       Write COMMAND's stderr lines to FILE, tagged with their sequence number
+  --stdout-file FILE       Write COMMAND's stdout lines to FILE, tagged with their sequence number
   --timezone TZ            Use specific timezone (e.g., UTC, EST, PST)
   -u, --utc                Use UTC time instead of local time
 
+Replay options (pass the same format options the log was written with):
+  --keep-timestamps        Print lines with their original timestamps
+  --max-gap SECS           Never wait longer than SECS between lines
+  --seek OFFSET            Start OFFSET ([[HH:]MM:]SS) into the log
+  --speed FACTOR           Playback speed multiplier (default: 1)
+
 Format specifiers (strftime compatible):
   %Y  4-digit year         %m  Month (01-12)        %d  Day (01-31)
   %H  Hour (00-23)         %M  Minute (00-59)       %S  Second (00-59)
   %3f Milliseconds         %6f Microseconds         %9f Nanoseconds
   %z  Timezone offset      %Z  Timezone name        %%  Literal %
+  %a  Short weekday        %A  Weekday name         %b  Short month name
+  %B  Month name           %p  AM/PM                %c  Locale date and time
 
 Examples:
   ls -la | {}                                             # Basic timestamping
   tail -f /var/log/messages | {} -r                       # Relative timestamps
   ping google.com | {} -f \"[%H:%M:%S.%3f]➜ \"              # Custom format
   dmesg | {} -i                                           # ISO format
+  dmesg | {} --locale fr_FR -f \"%a %d %B %X\"              # French names and layout
+  dmesg | {} --dmesg -i                                   # Kernel times as wall clock
+  make 2>&1 | {} -e --clock boottime                      # Seconds since boot
   make 2>&1 | {} -e                                       # Epoch timestamps
   tail -f app.log | {} -r -m                              # Relative monotonic
   cat file.txt | {} --delta                               # Show time between lines
   ping host | {} --color --microseconds                   # Colored with microseconds
   command | {} --prefix-only                              # Only timestamps
   make 2>&1 | {} -o build.log                             # Append to file
+  {} --label web web.fifo --label db db.fifo              # Merge two pipes, labelled
+  {} --input-encoding windows-1252 legacy.log             # Legacy log as UTF-8
   tail -f app.log | {} -o logs/app.log --force-overwrite  # Overwrite file
   ping host | {} -o network.log                           # Append to network.log
+  worker | {} -o jobs.log --lock --fsync 500              # Shared file, synced twice a second
+  make 2>&1 | {} -o build.log.zst                         # Compressed while streaming
+  {} -o build.log --banner -- make                        # Who built what, where, and how it ended
+  make 2>&1 | {} --passthrough -o build.log               # Raw terminal, stamped file
+  {} --pty -- cargo build                                 # Keep colors and line buffering
+  {} --stdout-file out.log --stderr-file err.log -- make  # Streams apart, shared sequence
+  make 2>&1 | {} --sqlite builds.db                       # Queryable build log
+  make 2>&1 | {} --html build.html                        # Shareable build report
+  make 2>&1 | {} --otlp http://otel:4318/v1/logs          # Ship to a collector
+  app | {} --json --sink tcp://127.0.0.1:5170             # Feed fluent-bit or vector
+  tail -f app.log | {} --metrics-file tss.prom            # Alert on quiet streams
+  {} replay --speed 4 --max-gap 2 build.log               # Replay a build log
+  app | {} | grep -v DEBUG | {} --stamp-again            # Per-stage latency
+  make 2>&1 | {} --stamp-at both                          # When slow lines started and ended
+  make 2>&1 | {} --match error -o build.log,unfiltered    # Quiet terminal, full log
+  make 2>&1 | {} --status > build.log                     # Progress while logging
+  make 2>&1 | {} --max-line-bytes 200 -o build.log,keep   # Short terminal lines, full log
+  ./retry.sh | {} --dedupe-ignore-digits                  # Collapse retry spam
+  java -jar app.jar | {} --multiline '^(\\s|Caused)'       # Group stack traces
 
 Note: --relative and --delta are mutually exclusive
-      Output files are appended to by default, use --force-overwrite to replace\n",
+      Colors honour NO_COLOR and FORCE_COLOR; set a theme in TSS_THEME or ~/.config/tss/theme
+      as key=color pairs (timestamp, label, heat=C1,C2,C3), e.g. timestamp=#5fafff:heat=yellow,208,red
+      INPUT is a file, a named pipe or - for stdin (default: stdin)
+      Output files are appended to by default, use --force-overwrite to replace
+      An output file name may end in options: ,append ,overwrite ,color (colored timestamps),
+      ,plain (escape sequences stripped), ,unfiltered (keep lines hidden by --match/--exclude)
+      or ,truncate ,wrap ,keep (--long-lines for this file),
+      e.g. --stderr-file err.log,overwrite,plain
+      Lines hidden by --match/--exclude still count for --delta, statistics and the sinks
+      Without --pty, COMMAND's stderr lines are stamped separately and written to stderr
+      SIGHUP or SIGUSR1 reopens the output files (only SIGUSR1 with --pty)
+      SIGTERM or SIGINT stops reading and flushes all outputs before exiting; without --pty,
+      SIGTERM is passed on to COMMAND and tss exits once its output ends
+      When running COMMAND its exit status is returned\n",
             program_name, program_name, program_name, program_name, program_name, 
             program_name, program_name, program_name, program_name, program_name,
-            program_name, program_name, program_name, program_name
+            program_name, program_name, program_name, program_name, program_name,
+            program_name, program_name, program_name, program_name, program_name,
+            program_name, program_name, program_name, program_name, program_name,
+            program_name, program_name, program_name, program_name, program_name,
+            program_name, program_name, program_name, program_name, program_name,
+            program_name, program_name, program_name, program_name, program_name,
+            program_name
         );
     }
 }
@@ -244,6 +1098,27 @@ impl FormatType {
     }
 }
 
+/// The moment a record was read, captured once so that every output and
+/// sink agrees on it
+#[derive(Clone, Copy)]
+struct Stamp {
+    wall: SystemTime,
+    mono: Instant,
+    // Reading of the --clock source, which timestamps are formatted from
+    clock: SystemTime,
+}
+
+impl Stamp {
+    fn now(clock: Clock) -> Self {
+        let wall = SystemTime::now();
+        Self {
+            wall,
+            mono: Instant::now(),
+            clock: if clock == Clock::Realtime { wall } else { clock.now() },
+        }
+    }
+}
+
 struct TimeFormatter {
     format_type: FormatType,
     utc: bool,
@@ -253,10 +1128,10 @@ struct TimeFormatter {
     last_time: Option<SystemTime>,
     last_instant: Option<Instant>,
     custom_format: Option<String>,
+    locale: Option<Locale>,
     timestamp_buf: String,
-    color: bool,
-    color_prefix: &'static str,
-    color_suffix: &'static str,
+    // Last delta formatted, for heat colors
+    last_delta: Duration,
 }
 
 impl TimeFormatter {
@@ -269,12 +1144,6 @@ impl TimeFormatter {
             None
         };
         
-        let (color_prefix, color_suffix) = if config.color {
-            ("\x1b[36m", "\x1b[0m") // Cyan color
-        } else {
-            ("", "")
-        };
-        
         Self {
             format_type,
             utc: config.utc,
@@ -284,66 +1153,42 @@ impl TimeFormatter {
             last_time: None,
             last_instant: None,
             custom_format,
+            locale: config.locale,
             timestamp_buf: String::with_capacity(128),
-            color: config.color,
-            color_prefix,
-            color_suffix,
+            last_delta: Duration::ZERO,
         }
     }
     
     #[inline]
-    fn format_timestamp(&mut self, monotonic: bool) -> &str {
-        self.format_timestamp_impl(monotonic, true)
-    }
-    
-    #[inline]
-    fn format_timestamp_no_color(&mut self, monotonic: bool) -> &str {
-        self.format_timestamp_impl(monotonic, false)
-    }
-    
-    #[inline]
-    fn format_timestamp_impl(&mut self, monotonic: bool, use_color: bool) -> &str {
+    fn format_timestamp(&mut self, stamp: Stamp, monotonic: bool) -> &str {
         self.timestamp_buf.clear();
         
         match &self.format_type {
             FormatType::Delta => {
                 let duration = if monotonic {
-                    let instant = Instant::now();
+                    let instant = stamp.mono;
                     let duration = if let Some(last) = self.last_instant {
                         instant.duration_since(last)
                     } else {
                         // Initialize with current time for first call
                         self.last_instant = Some(instant);
-                        return if use_color && self.color {
-                            self.timestamp_buf.push_str(self.color_prefix);
-                            self.timestamp_buf.push_str("0.000000");
-                            self.timestamp_buf.push_str(self.color_suffix);
-                            &self.timestamp_buf
-                        } else {
-                            "0.000000"
-                        };
+                        return "0.000000";
                     };
                     self.last_instant = Some(instant);
                     duration
                 } else {
-                    let time = SystemTime::now();
+                    let time = stamp.clock;
                     let duration = if let Some(last) = self.last_time {
                         time.duration_since(last).unwrap_or_default()
                     } else {
                         // Initialize with current time for first call
                         self.last_time = Some(time);
-                        return if use_color && self.color {
-                            self.timestamp_buf.push_str(self.color_prefix);
-                            self.timestamp_buf.push_str("0.000000");
-                            self.timestamp_buf.push_str(self.color_suffix);
-                            &self.timestamp_buf
-                        } else {
-                            "0.000000"
-                        };
+                        return "0.000000";
                     };
                     self.last_time = Some(time);
                     duration
                 };
+                self.last_delta = duration;
                 
                 let total_us = duration.as_micros();
                 use std::fmt::Write;
@@ -352,28 +1197,28 @@ impl TimeFormatter {
             },
             
             FormatType::Epoch => {
-                let now = SystemTime::now();
+                let now = stamp.clock;
                 let secs = now.duration_since(UNIX_EPOCH).unwrap().as_secs();
                 use std::fmt::Write;
                 let _ = write!(self.timestamp_buf, "{}", secs);
             },
             
             FormatType::EpochUs => {
-                let now = SystemTime::now();
+                let now = stamp.clock;
                 let us = now.duration_since(UNIX_EPOCH).unwrap().as_micros();
                 use std::fmt::Write;
                 let _ = write!(self.timestamp_buf, "{}", us);
             },
             
             FormatType::EpochNs => {
-                let now = SystemTime::now();
+                let now = stamp.clock;
                 let ns = now.duration_since(UNIX_EPOCH).unwrap().as_nanos();
                 use std::fmt::Write;
                 let _ = write!(self.timestamp_buf, "{}", ns);
             },
             
             FormatType::CommonISO => {
-                let now = SystemTime::now();
+                let now = stamp.clock;
                 if self.utc {
                     let dt: DateTime<Utc> = now.into();
                     use std::fmt::Write;
@@ -390,7 +1235,7 @@ impl TimeFormatter {
             },
             
             FormatType::CommonISOMs => {
-                let now = SystemTime::now();
+                let now = stamp.clock;
                 if self.utc {
                     let dt: DateTime<Utc> = now.into();
                     use std::fmt::Write;
@@ -409,7 +1254,7 @@ impl TimeFormatter {
             },
             
             FormatType::CommonISOUs => {
-                let now = SystemTime::now();
+                let now = stamp.clock;
                 if self.utc {
                     let dt: DateTime<Utc> = now.into();
                     use std::fmt::Write;
@@ -428,7 +1273,7 @@ impl TimeFormatter {
             },
             
             FormatType::CommonISONs => {
-                let now = SystemTime::now();
+                let now = stamp.clock;
                 if self.utc {
                     let dt: DateTime<Utc> = now.into();
                     use std::fmt::Write;
@@ -447,7 +1292,7 @@ impl TimeFormatter {
             },
             
             FormatType::ISO8601 => {
-                let now = SystemTime::now();
+                let now = stamp.clock;
                 if self.utc {
                     let dt: DateTime<Utc> = now.into();
                     use std::fmt::Write;
@@ -463,11 +1308,11 @@ impl TimeFormatter {
                 if self.relative {
                     // Handle relative timestamps with custom format
                     let duration = if monotonic {
-                        let now = Instant::now();
+                        let now = stamp.mono;
                         let start = self.start_instant.get_or_insert(now);
                         now.duration_since(*start)
                     } else {
-                        let now = SystemTime::now();
+                        let now = stamp.clock;
                         let start = self.start_time.get_or_insert(now);
                         now.duration_since(*start).unwrap_or_default()
                     };
@@ -483,8 +1328,7 @@ impl TimeFormatter {
                         let dt = Utc.with_ymd_and_hms(1970, 1, 1, hours, mins, secs).unwrap()
                             .with_nanosecond(subsec_nanos).unwrap();
                         
-                        use std::fmt::Write;
-                        let _ = write!(self.timestamp_buf, "{}", dt.format(fmt));
+                        push_formatted(&mut self.timestamp_buf, &dt, fmt, self.locale);
                     } else {
                         let total_ms = duration.as_millis();
                         use std::fmt::Write;
@@ -493,128 +1337,852 @@ impl TimeFormatter {
                     }
                 } else {
                     // Handle absolute timestamps with custom format
-                    let now = SystemTime::now();
+                    let now = stamp.clock;
                     if let Some(ref fmt) = self.custom_format {
                         if self.utc {
                             let dt: DateTime<Utc> = now.into();
-                            use std::fmt::Write;
-                            let _ = write!(self.timestamp_buf, "{}", dt.format(fmt));
+                            push_formatted(&mut self.timestamp_buf, &dt, fmt, self.locale);
                         } else {
                             let dt: DateTime<Local> = now.into();
-                            use std::fmt::Write;
-                            let _ = write!(self.timestamp_buf, "{}", dt.format(fmt));
+                            push_formatted(&mut self.timestamp_buf, &dt, fmt, self.locale);
                         }
                     }
                 }
             },
         }
         
-        // Add color codes if needed - do this outside the timestamp buffer
-        // to avoid reallocation on every call
-        if use_color && self.color {
-            // Create a temporary string with color codes
-            let colored = format!("{}{}{}", self.color_prefix, self.timestamp_buf, self.color_suffix);
-            self.timestamp_buf = colored;
+        &self.timestamp_buf
+    }
+    
+    /// Append `timestamp` to `out`, wrapped in color codes when enabled.
+    /// Deltas are colored by size.
+    #[inline]
+    fn push_timestamp(&self, out: &mut Vec<u8>, theme: Option<&Theme>, timestamp: &str) {
+        let color = match theme {
+            Some(theme) if matches!(self.format_type, FormatType::Delta) => theme.heat(self.last_delta),
+            Some(theme) => &theme.timestamp,
+            None => "",
+        };
+        push_colored(out, color, timestamp);
+    }
+}
+
+/// Append `dt` in strftime format `fmt`, with names and the %c/%x/%X
+/// layouts in `locale` when one is set
+fn push_formatted<Tz: TimeZone>(buf: &mut String, dt: &DateTime<Tz>, fmt: &str, locale: Option<Locale>)
+where
+    Tz::Offset: std::fmt::Display,
+{
+    use std::fmt::Write;
+    let _ = match locale {
+        Some(locale) => write!(buf, "{}", dt.format_localized(fmt, locale)),
+        None => write!(buf, "{}", dt.format(fmt)),
+    };
+}
+
+/// Whether continuation lines of a multiline record carry a prefix
+#[derive(Clone, Copy, PartialEq)]
+enum Continuation {
+    Pad,  // Blank padding as wide as the timestamp
+    None, // Line is written as-is
+}
+
+/// An output file and which lines it receives
+struct FileOutput {
+    log: LogFile,
+    stream: FileStream,
+    color: FileColor,
+    unfiltered: bool,
+    long_lines: LongLines,
+    encoding: Option<OutputEncoding>,
+    encoded: Vec<u8>,
+}
+
+impl FileOutput {
+    /// Whether `record` belongs in this file
+    fn wants(&self, record: &Record) -> bool {
+        if record.hidden && !self.unfiltered {
+            return false;
+        }
+        match self.stream {
+            FileStream::Stdout => record.stream == Stream::Stdout,
+            FileStream::Stderr => record.stream == Stream::Stderr,
+            FileStream::All | FileStream::Combined => true,
+        }
+    }
+    
+    fn write(&mut self, line: &[u8], buffered: bool) -> io::Result<()> {
+        let line = match self.encoding {
+            Some(encoding) => {
+                encoding.encode(&String::from_utf8_lossy(line), &mut self.encoded);
+                &self.encoded
+            }
+            None => line,
+        };
+        self.log.write_lines(line)?;
+        // Flush file writer if not buffered
+        if !buffered {
+            self.log.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes rendered lines to stdout (stderr for the stderr of a piped
+/// command) and to every output file
+struct Output {
+    stdout: BufWriter<io::Stdout>,
+    stderr: BufWriter<io::Stderr>,
+    files: Vec<FileOutput>,
+    buffered: bool,
+    // Stdout already receives the raw input through a Passthrough reader
+    passthrough: bool,
+    // --status line to keep out of the way of stderr lines
+    status: Option<Status>,
+    line: Vec<u8>,
+}
+
+impl Output {
+    /// Write a text line of `record`, colored on the terminal and in files
+    /// written with ,color
+    fn write_text(
+        &mut self,
+        config: &Config,
+        formatter: &TimeFormatter,
+        prefix: Prefix,
+        line: &str,
+        record: &Record,
+    ) -> io::Result<()> {
+        if !self.passthrough && !record.hidden {
+            let to_stderr = config.runs_piped() && record.stream == Stream::Stderr;
+            let status_hidden = self.status.as_ref().filter(|_| to_stderr).map(Status::hide);
+            for piece in split_long(config, line, config.long_lines) {
+                render_text(&mut self.line, config, formatter, prefix, &piece, config.color);
+                if to_stderr {
+                    self.stderr.write_all(&self.line)?;
+                } else {
+                    self.stdout.write_all(&self.line)?;
+                }
+            }
+            if status_hidden.is_some() {
+                self.stderr.flush()?;
+            }
+        }
+        
+        for file in &mut self.files {
+            if !file.wants(record) {
+                continue;
+            }
+            // Files other than --output carry the shared sequence number,
+            // and the stream when they hold more than one
+            let tag;
+            let prefix = match prefix {
+                Prefix::Stamp(timestamp, _) if file.stream != FileStream::All => {
+                    tag = match file.stream {
+                        FileStream::Combined => format!("#{} {}", record.seq, record.stream),
+                        _ => format!("#{}", record.seq),
+                    };
+                    Prefix::Stamp(timestamp, Some(&tag))
+                }
+                prefix => prefix,
+            };
+            let stripped;
+            let line = if file.color == FileColor::Plain && line.contains('\x1b') {
+                stripped = theme::strip_escapes(line);
+                &stripped
+            } else {
+                line
+            };
+            for piece in split_long(config, line, file.long_lines) {
+                render_text(&mut self.line, config, formatter, prefix, &piece, file.color == FileColor::Color);
+                file.write(&self.line, self.buffered)?;
+            }
+        }
+        self.flush_terminal()
+    }
+    
+    fn write_json(&mut self, config: &Config, record: &Record, repeat: Option<&Repeat>) -> io::Result<()> {
+        render_json(&mut self.line, config, record, repeat);
+        if !self.passthrough && !record.hidden {
+            self.stdout.write_all(&self.line)?;
+        }
+        for file in &mut self.files {
+            if file.wants(record) {
+                file.write(&self.line, self.buffered)?;
+            }
+        }
+        self.flush_terminal()
+    }
+    
+    /// Write a --banner line to every file and, if asked, to stdout. With
+    /// --passthrough stdout carries the input unmodified, so it never gets one.
+    fn write_banner(&mut self, line: &[u8], to_stdout: bool) -> io::Result<()> {
+        if to_stdout && !self.passthrough {
+            self.stdout.write_all(line)?;
+        }
+        for file in &mut self.files {
+            file.write(line, self.buffered)?;
+        }
+        self.flush_terminal()
+    }
+    
+    // Flush stdout and stderr when unbuffered
+    fn flush_terminal(&mut self) -> io::Result<()> {
+        if !self.buffered && !self.passthrough {
+            self.stdout.flush()?;
+            self.stderr.flush()?;
+        }
+        Ok(())
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()?;
+        self.stderr.flush()?;
+        for file in &mut self.files {
+            file.log.sync()?;
+        }
+        Ok(())
+    }
+}
+
+// A --multiline record waiting for continuation lines is let go after this
+// long without input, so the last record of a live stream reaches --json
+// and the sinks
+const MULTILINE_IDLE: Duration = Duration::from_millis(500);
+
+/// Turns input lines into records and hands them to stdout, the output
+/// file and any structured sinks
+struct Stamper<'a> {
+    config: &'a Config,
+    formatter: TimeFormatter,
+    output: Output,
+    sinks: Vec<Box<dyn Sink>>,
+    deduper: Option<Deduper>,
+    restamper: Option<Restamper>,
+    // Record still accepting --multiline continuation lines
+    pending: Option<Record>,
+    // Visible width of the last timestamp, zero until the first record
+    pad_width: usize,
+    seq: u64,
+    // Input lines seen, for the end banner
+    lines: u64,
+    // Whether the last record was hidden by --match/--exclude
+    hidden: bool,
+    last: Option<Stamp>,
+    stream: Stream,
+    banner: Option<Banner>,
+    status: Option<Status>,
+    // Wall-clock boot time when converting dmesg timestamps
+    dmesg_boot: Option<SystemTime>,
+}
+
+impl Stamper<'_> {
+    /// Process one input line. `completed` is the time the newline arrived
+    /// when it is shown next to the first-byte `stamp`.
+    fn line(&mut self, line: String, stamp: Stamp, completed: Option<Stamp>) -> io::Result<()> {
+        let config = self.config;
+        self.lines += 1;
+        if let Some(ref status) = self.status {
+            status.line(&line);
+        }
+        let (line, stamp) = self.convert_dmesg(line, stamp);
+        // An upstream tss's banners are copied, not stamped; JSON output
+        // has its own banner and no place for them
+        if config.stamp_again && banner::is_text_banner(&line) {
+            if config.json {
+                return Ok(());
+            }
+            self.flush_pending()?;
+            return self.output.write_banner(format!("{}\n", line).as_bytes(), true);
+        }
+        
+        // Only lines from the same input continue a record
+        let is_continuation = self.pending.as_ref().is_some_and(|record| record.stream == self.stream)
+            && config.multiline.as_ref().is_some_and(|re| re.is_match(&line));
+        if is_continuation {
+            if let Some(ref mut record) = self.pending {
+                if !config.json && !config.prefix_only {
+                    let label_width = config.show_labels().then(|| self.stream.name().chars().count() + 3);
+                    let prefix = Prefix::Continuation(self.pad_width, label_width.unwrap_or(0));
+                    self.output.write_text(config, &self.formatter, prefix, &line, record)?;
+                }
+                record.lines.push(line);
+            }
+            return Ok(());
+        }
+        self.flush_pending()?;
+        
+        let timestamp = self.formatter.format_timestamp(stamp, config.monotonic).to_string();
+        // Upstream timestamps differ on every line, so repeats are judged
+        // on the text after them
+        let (line, upstream) = self.split_upstream(line, &timestamp);
+        if let Some(ref mut deduper) = self.deduper {
+            if deduper.is_repeat(&line, &self.stream, &timestamp, stamp) {
+                deduper.add_repeat(&timestamp, stamp);
+                return Ok(());
+            }
+            if let Some(repeat) = deduper.take_repeat() {
+                self.write_repeat(&repeat)?;
+            }
         }
         
-        &self.timestamp_buf
+        let mut record = self.new_record(stamp, timestamp, line);
+        // Hidden records are still timed, counted and sent to the sinks
+        record.hidden = !config.filter.passes(&record.lines[0]);
+        self.hidden = record.hidden;
+        record.completed = completed.map(|done| self.formatter.format_timestamp(done, config.monotonic).to_string());
+        if !config.json {
+            let stamps = match record.completed {
+                Some(ref done) => format!("{}{}{}", record.timestamp, config.separator, done),
+                None => record.timestamp.clone(),
+            };
+            // An upstream prefix chain is extended rather than nested
+            let prefix = match upstream {
+                Some(ref upstream) => format!(
+                    "{}{}{}{}{}",
+                    upstream.prefix,
+                    config.separator,
+                    stamps,
+                    config.separator,
+                    format_seconds(upstream.latency_ns)
+                ),
+                None => stamps,
+            };
+            self.pad_width = prefix.chars().count();
+            let label = config.show_labels().then_some(self.stream.name());
+            self.output.write_text(config, &self.formatter, Prefix::Stamp(&prefix, label), &record.lines[0], &record)?;
+        }
+        record.upstream = upstream;
+        self.pending = Some(record);
+        if config.multiline.is_none() {
+            self.flush_pending()?;
+        }
+        Ok(())
+    }
+    
+    /// Flush everything still held back and close the sinks. `exit_code`
+    /// is that of the wrapped command, `exit_status` what tss exits with.
+    fn finish(&mut self, exit_code: Option<i32>, exit_status: i32) -> io::Result<()> {
+        if let Some(ref status) = self.status {
+            status.finish();
+        }
+        self.flush_pending()?;
+        if let Some(repeat) = self.deduper.as_mut().and_then(Deduper::take_repeat) {
+            self.write_repeat(&repeat)?;
+        }
+        let end = SystemTime::now();
+        if let Some(ref banner) = self.banner {
+            let line = banner.end_line(end, self.lines, exit_status);
+            self.output.write_banner(&line, banner.target == BannerTarget::All)?;
+        }
+        self.output.flush()?;
+        if let Some(summary) = self.restamper.as_mut().and_then(Restamper::summary) {
+            eprintln!("tss: {}", summary);
+        }
+        for sink in &mut self.sinks {
+            sink.finish(end, exit_code)?;
+        }
+        Ok(())
     }
+    
+    fn new_record(&mut self, stamp: Stamp, timestamp: String, line: String) -> Record {
+        self.pad_width = timestamp.chars().count().max(1);
+        let delta = self
+            .last
+            .map(|last| match self.dmesg_boot {
+                // Kernel timestamps, not read times, set the spacing
+                Some(_) => stamp.clock.duration_since(last.clock).unwrap_or_default(),
+                None => stamp.mono.duration_since(last.mono),
+            })
+            .unwrap_or_default();
+        self.last = Some(stamp);
+        self.seq += 1;
+        Record {
+            seq: self.seq,
+            time: stamp.wall,
+            delta,
+            stream: self.stream.clone(),
+            timestamp,
+            lines: vec![line],
+            upstream: None,
+            completed: None,
+            hidden: false,
+        }
+    }
+    
+    /// With --dmesg, replace a "[seconds since boot]" prefix on `line` with
+    /// the wall-clock time it corresponds to
+    fn convert_dmesg(&self, line: String, stamp: Stamp) -> (String, Stamp) {
+        let Some(boot) = self.dmesg_boot else {
+            return (line, stamp);
+        };
+        let Some((since_boot, text)) = clock::split_dmesg(&line) else {
+            return (line, stamp);
+        };
+        // Lines too far in the future for a date pass through unconverted
+        let Some(time) = clock::dmesg_time(boot, since_boot) else {
+            return (line, stamp);
+        };
+        let stamp = Stamp {
+            wall: time,
+            clock: time,
+            ..stamp
+        };
+        (text.to_string(), stamp)
+    }
+    
+    /// With --stamp-again, take the prefix an earlier tss put on `line` off
+    /// the text and measure the latency from it
+    fn split_upstream(&mut self, line: String, timestamp: &str) -> (String, Option<UpstreamStamp>) {
+        let Some(ref mut restamper) = self.restamper else {
+            return (line, None);
+        };
+        let Some(upstream) = restamper.split(&line) else {
+            return (line, None);
+        };
+        let Some(latency_ns) = restamper.latency(&upstream, timestamp) else {
+            return (line, None);
+        };
+        let stamp = UpstreamStamp {
+            prefix: upstream.prefix.to_string(),
+            latency_ns,
+        };
+        (upstream.text.to_string(), Some(stamp))
+    }
+    
+    /// Hand a completed record to the JSON output and the sinks
+    fn flush_pending(&mut self) -> io::Result<()> {
+        if let Some(record) = self.pending.take() {
+            if self.config.json {
+                self.output.write_json(self.config, &record, None)?;
+            }
+            for sink in &mut self.sinks {
+                sink.write_record(&record)?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Write the "last message repeated" summary for a run of duplicates
+    fn write_repeat(&mut self, repeat: &Repeat) -> io::Result<()> {
+        let config = self.config;
+        let mut record = self.new_record(repeat.last_stamp(), repeat.last_seen.clone(), repeat.message());
+        // The line that ends a run may come from another stream
+        record.stream = repeat.stream.clone();
+        // Repeats of a hidden line stay hidden too
+        record.hidden = self.hidden;
+        if config.json {
+            self.output.write_json(config, &record, Some(repeat))?;
+        } else {
+            let message = format!("{} (first seen {})", record.lines[0], repeat.first_seen);
+            self.output.write_text(config, &self.formatter, Prefix::Stamp(&record.timestamp, None), &message, &record)?;
+        }
+        for sink in &mut self.sinks {
+            sink.write_record(&record)?;
+        }
+        Ok(())
+    }
+}
+
+/// Copies everything read from the input to stdout (or stderr) unmodified,
+/// so partial lines and carriage returns reach the terminal as soon as they
+/// arrive.
+struct Passthrough<R, W> {
+    inner: R,
+    out: W,
+    buffered: bool,
+}
+
+impl<R: io::Read, W: Write> io::Read for Passthrough<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.out.write_all(&buf[..n])?;
+        if !self.buffered {
+            self.out.flush()?;
+        }
+        Ok(n)
+    }
+}
+
+/// What precedes the text of a rendered line
+#[derive(Clone, Copy)]
+enum Prefix<'a> {
+    /// A new record: its timestamp and, when shown, the input label
+    Stamp(&'a str, Option<&'a str>),
+    /// A --multiline continuation line, padded to the given timestamp and
+    /// "[label] " widths
+    Continuation(usize, usize),
+}
+
+/// Render one text line, with theme colors when `use_color` is set
+fn render_text(
+    out: &mut Vec<u8>,
+    config: &Config,
+    formatter: &TimeFormatter,
+    prefix: Prefix,
+    line: &str,
+    use_color: bool,
+) {
+    out.clear();
+    let theme = config.theme.as_ref().filter(|_| use_color);
+    match prefix {
+        Prefix::Stamp(ts, label) => {
+            formatter.push_timestamp(out, theme, ts);
+            if !config.prefix_only {
+                out.extend_from_slice(config.separator.as_bytes());
+                if let Some(label) = label {
+                    let color = theme.map_or("", |theme| theme.label.as_str());
+                    push_colored(out, color, &format!("[{}]", label));
+                    out.push(b' ');
+                }
+            }
+        }
+        Prefix::Continuation(pad_width, label_width) if config.continuation == Continuation::Pad => {
+            out.resize(pad_width, b' ');
+            out.extend_from_slice(config.separator.as_bytes());
+            out.resize(out.len() + label_width, b' ');
+        }
+        Prefix::Continuation(..) => {}
+    }
+    if !config.prefix_only {
+        match theme {
+            Some(_) if !config.highlights.is_empty() => filter::push_highlighted(out, line, &config.highlights),
+            _ => out.extend_from_slice(line.as_bytes()),
+        }
+    }
+    out.push(b'\n');
+}
+
+/// The lines `line` is written as under --max-line-bytes and `policy`
+fn split_long<'a>(config: &Config, line: &'a str, policy: LongLines) -> Vec<Cow<'a, str>> {
+    match config.max_line_bytes {
+        Some(max) => longline::split(line, max, policy),
+        None => vec![Cow::Borrowed(line)],
+    }
+}
+
+/// Append `text` wrapped in the SGR sequence `color`, if there is one
+fn push_colored(out: &mut Vec<u8>, color: &str, text: &str) {
+    if color.is_empty() {
+        out.extend_from_slice(text.as_bytes());
+    } else {
+        out.extend_from_slice(color.as_bytes());
+        out.extend_from_slice(text.as_bytes());
+        out.extend_from_slice(theme::RESET.as_bytes());
+    }
+}
+
+/// Render a record as a single JSON object terminated by a newline
+fn render_json(out: &mut Vec<u8>, config: &Config, record: &Record, repeat: Option<&Repeat>) {
+    out.clear();
+    // Lets the stdout and stderr files of a command be merged in order
+    let seq = config.runs_piped();
+    push_json_record(out, record, repeat, config.show_labels() || seq, seq);
+}
+
+/// Append `record` as a JSON object and a newline, with its "source" and
+/// "seq" only when asked for
+pub(crate) fn push_json_record(out: &mut Vec<u8>, record: &Record, repeat: Option<&Repeat>, source: bool, seq: bool) {
+    out.extend_from_slice(b"{\"timestamp\":");
+    push_json_string(out, &record.timestamp);
+    if let Some(ref completed) = record.completed {
+        out.extend_from_slice(b",\"completed\":");
+        push_json_string(out, completed);
+    }
+    if source {
+        out.extend_from_slice(b",\"source\":");
+        push_json_string(out, record.stream.name());
+    }
+    if seq {
+        out.extend_from_slice(format!(",\"seq\":{}", record.seq).as_bytes());
+    }
+    out.extend_from_slice(b",\"line\":");
+    push_json_string(out, &record.lines.join("\n"));
+    if let Some(ref upstream) = record.upstream {
+        out.extend_from_slice(b",\"upstream\":");
+        push_json_string(out, &upstream.prefix);
+        out.extend_from_slice(format!(",\"latency\":{:.6}", upstream.latency_ns as f64 / 1e9).as_bytes());
+    }
+    if let Some(repeat) = repeat {
+        out.extend_from_slice(format!(",\"repeated\":{}", repeat.count).as_bytes());
+        out.extend_from_slice(format!(",\"duration\":{:.6}", repeat.duration().as_secs_f64()).as_bytes());
+        out.extend_from_slice(b",\"first_seen\":");
+        push_json_string(out, &repeat.first_seen);
+        out.extend_from_slice(b",\"last_seen\":");
+        push_json_string(out, &repeat.last_seen);
+    }
+    out.extend_from_slice(b"}\n");
+}
+
+pub(crate) fn push_json_string(out: &mut Vec<u8>, s: &str) {
+    out.push(b'"');
+    for c in s.chars() {
+        match c {
+            '"' => out.extend_from_slice(b"\\\""),
+            '\\' => out.extend_from_slice(b"\\\\"),
+            '\n' => out.extend_from_slice(b"\\n"),
+            '\r' => out.extend_from_slice(b"\\r"),
+            '\t' => out.extend_from_slice(b"\\t"),
+            c if (c as u32) < 0x20 => {
+                out.extend_from_slice(format!("\\u{:04x}", c as u32).as_bytes());
+            }
+            c => {
+                let mut buf = [0u8; 4];
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    out.push(b'"');
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = Config::parse_args()?;
-    let mut formatter = TimeFormatter::new(&config);
+    if let Some(ref replay) = config.replay {
+        if let Err(e) = replay::run(&config, replay) {
+            eprintln!("Error: {}: {}", replay.input, e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    // Before any thread is spawned, so they all leave these signals alone
+    let wake = match signals::install(config.pty) {
+        Ok(wake) => wake,
+        Err(e) => {
+            eprintln!("Error: Failed to set up signal handling: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let formatter = TimeFormatter::new(&config);
     
-    let stdin = io::stdin();
     let stdout = io::stdout();
     
-    // Set up output file if specified
-    let mut file_writer = if let Some(ref output_path) = config.output_file {
-        // Create parent directories if they don't exist
-        if let Some(parent) = Path::new(output_path).parent() {
-            create_dir_all(parent)?;
+    // Read either our own stdin or the output of a command run under a pty;
+    // positional inputs and the pipes of a command run without one are read
+    // on threads of their own further down
+    let mut pipes = Vec::new();
+    let (input, mut child): (Box<dyn io::Read + Send>, _) = if config.pty {
+        match pty::spawn(&config.command) {
+            Ok(pty) => (Box::new(pty.master), Some(pty.child)),
+            Err(e) => {
+                eprintln!("Error: Failed to run {}: {}", config.command[0], e);
+                std::process::exit(1);
+            }
         }
-        
-        // Open file for writing (append by default, create/truncate if force_overwrite)
-        let file = if config.force_overwrite {
-            OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(output_path)?
-        } else {
-            OpenOptions::new()
-                .create(true)
-                .write(true)
-                .append(true)
-                .open(output_path)?
-        };
-        
-        Some(BufWriter::new(file))
+    } else if !config.command.is_empty() {
+        match input::spawn_command(&config.command) {
+            Ok((child, inputs)) => {
+                signals::forward_to(child.id());
+                pipes = inputs;
+                (Box::new(io::empty()), Some(child))
+            }
+            Err(e) => {
+                eprintln!("Error: Failed to run {}: {}", config.command[0], e);
+                std::process::exit(1);
+            }
+        }
+    } else if config.inputs.is_empty() {
+        (Box::new(Interruptible::new(io::stdin(), wake.try_clone()?)), None)
     } else {
-        None
+        (Box::new(io::empty()), None)
     };
     
+    let inputs = if config.runs_piped() { &pipes } else { &config.inputs };
+    
+    let mut files = Vec::new();
+    for spec in &config.output_files {
+        // An explicit --compress wins over the file extension
+        let compression = config.compress.unwrap_or_else(|| Compression::for_path(&spec.path));
+        let overwrite = spec.overwrite.unwrap_or(config.force_overwrite);
+        let log = LogFile::open(&spec.path, overwrite, config.lock, config.fsync, compression)?;
+        signals::on_reopen(log.reopener());
+        files.push(FileOutput {
+            log,
+            stream: spec.stream,
+            color: spec.color,
+            unfiltered: spec.unfiltered,
+            long_lines: spec.long_lines.unwrap_or(config.long_lines),
+            encoding: config.output_encoding,
+            encoded: Vec::new(),
+        });
+    }
+    
     // Use appropriate buffer sizes based on configuration
     let buffer_size = if config.buffered { 256 * 1024 } else { 0 };
-    let reader = BufReader::with_capacity(128 * 1024, stdin);
-    let mut writer = BufWriter::with_capacity(buffer_size, stdout);
+    // Passthrough copies the original bytes; only the stamped copy is decoded
+    let input: Box<dyn io::Read + Send> = if config.passthrough {
+        Box::new(Passthrough {
+            inner: input,
+            out: io::stdout(),
+            buffered: config.buffered,
+        })
+    } else {
+        input
+    };
+    let input: Box<dyn io::Read + Send> = match config.input_encoding {
+        Some(encoding) => Box::new(Decoding::new(input, encoding)),
+        None => input,
+    };
+    let reader = StampedLines::new(input, config.stamp_at, config.clock);
+    let status = if config.status { Status::start() } else { None };
+    let mut output = Output {
+        stdout: BufWriter::with_capacity(buffer_size, stdout),
+        stderr: BufWriter::with_capacity(buffer_size, io::stderr()),
+        files,
+        buffered: config.buffered,
+        passthrough: config.passthrough,
+        status: status.clone(),
+        line: Vec::new(),
+    };
+    
+    let run = RunInfo::new(
+        config.run_id.clone(),
+        (!config.command.is_empty()).then(|| config.command.join(" ")),
+    );
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    if let Some(ref path) = config.sqlite {
+        match SqliteSink::open(path, &run) {
+            Ok(sink) => sinks.push(Box::new(sink)),
+            Err(e) => {
+                eprintln!("Error: Failed to open {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(ref path) = config.html {
+        match HtmlSink::create(path, config.html_steps.clone(), &run) {
+            Ok(sink) => sinks.push(Box::new(sink)),
+            Err(e) => {
+                eprintln!("Error: Failed to open {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(ref endpoint) = config.otlp {
+        let otlp = OtlpConfig {
+            endpoint: endpoint.clone(),
+            protocol: config.otlp_protocol,
+            service_name: config.otlp_service.clone(),
+            attributes: config.otlp_attributes.clone(),
+        };
+        match OtlpSink::new(otlp, &run) {
+            Ok(sink) => sinks.push(Box::new(sink)),
+            Err(e) => {
+                eprintln!("Error: Invalid --otlp endpoint: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    for url in &config.sinks {
+        let labels = config.show_labels() || config.runs_piped();
+        match NetSink::new(url, config.json, &config.separator, labels, config.sink_overflow) {
+            Ok(sink) => sinks.push(Box::new(sink)),
+            Err(e) => {
+                eprintln!("Error: Invalid --sink: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(ref path) = config.metrics_file {
+        match MetricsSink::new(path, config.metrics_interval) {
+            Ok(sink) => sinks.push(Box::new(sink)),
+            Err(e) => {
+                eprintln!("Error: Failed to write {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    
+    let banner = config.banner.map(|target| Banner::new(target, config.json, run.start));
+    if let Some(ref banner) = banner {
+        output.write_banner(&banner.start_line(&run), banner.target == BannerTarget::All)?;
+    }
     
-    let separator_bytes = config.separator.as_bytes();
-    let newline = b"\n";
+    let mut stamper = Stamper {
+        config: &config,
+        formatter,
+        output,
+        sinks,
+        deduper: config.dedupe.then(|| {
+            Deduper::new(config.dedupe_ignore_digits, config.dedupe_ignore.clone())
+        }),
+        restamper: config.stamp_again.then(|| Restamper::new(&config)),
+        pending: None,
+        pad_width: 0,
+        seq: 0,
+        lines: 0,
+        hidden: false,
+        last: None,
+        stream: if config.pty { Stream::Stdout } else { Stream::Stdin },
+        dmesg_boot: config.dmesg.then(clock::boot_time),
+        banner,
+        status,
+    };
     
-    for line_result in reader.lines() {
-        let line = line_result?;
-        let timestamp = formatter.format_timestamp(config.monotonic);
-        
-        // Prepare the complete output line
-        let mut output_line = Vec::new();
-        output_line.extend_from_slice(timestamp.as_bytes());
-        
-        if !config.prefix_only {
-            output_line.extend_from_slice(separator_bytes);
-            output_line.extend_from_slice(line.as_bytes());
+    let mut input_failed = false;
+    if inputs.is_empty() && config.multiline.is_none() {
+        for line_result in reader {
+            let line = line_result?;
+            stamper.line(line.text, line.stamp, line.completed)?;
         }
-        
-        output_line.extend_from_slice(newline);
-        
-        // Write to stdout
-        writer.write_all(&output_line)?;
-        
-        // Write to file if specified (without color codes for clean file output)
-        if let Some(ref mut file_writer) = file_writer {
-            if config.color {
-                // Strip color codes for file output
-                let clean_timestamp = formatter.format_timestamp_no_color(config.monotonic);
-                let mut clean_output = Vec::new();
-                clean_output.extend_from_slice(clean_timestamp.as_bytes());
-                
-                if !config.prefix_only {
-                    clean_output.extend_from_slice(separator_bytes);
-                    clean_output.extend_from_slice(line.as_bytes());
+    } else {
+        let (events, received) = mpsc::sync_channel(1024);
+        let streams: Vec<Stream> = if inputs.is_empty() {
+            // With --multiline our own input is read on a thread as well,
+            // so a held-back record can be let go while the input is quiet
+            thread::spawn(move || input::send_lines(reader, 0, &events));
+            vec![stamper.stream.clone()]
+        } else {
+            for (index, input) in inputs.iter().enumerate() {
+                input.spawn(index, &config, wake.try_clone()?, events.clone());
+            }
+            drop(events);
+            inputs
+                .iter()
+                .map(|input| input.stream.clone().unwrap_or_else(|| Stream::Input(input.name().into())))
+                .collect()
+        };
+        loop {
+            let event = if stamper.pending.is_some() {
+                match received.recv_timeout(MULTILINE_IDLE) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => {
+                        stamper.flush_pending()?;
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
                 }
-                
-                clean_output.extend_from_slice(newline);
-                file_writer.write_all(&clean_output)?;
             } else {
-                file_writer.write_all(&output_line)?;
-            }
-            
-            // Flush file writer if not buffered
-            if !config.buffered {
-                file_writer.flush()?;
+                match received.recv() {
+                    Ok(event) => event,
+                    Err(_) => break,
+                }
+            };
+            match event {
+                Event::Line(index, line) => {
+                    stamper.stream = streams[index].clone();
+                    stamper.line(line.text, line.stamp, line.completed)?;
+                }
+                Event::Error(index, e) => {
+                    eprintln!("tss: {}: {}", streams[index], e);
+                    input_failed = true;
+                }
             }
         }
-        
-        // Flush stdout when unbuffered
-        if !config.buffered {
-            writer.flush()?;
-        }
     }
     
-    writer.flush()?;
-    if let Some(ref mut file_writer) = file_writer {
-        file_writer.flush()?;
+    let exit_code = match child {
+        Some(ref mut child) => Some(pty::exit_code(child.wait()?)),
+        None => None,
+    };
+    // A command's own status, else the signal that stopped us (flushed
+    // cleanly after SIGTERM/SIGINT), else whether every input was read
+    let exit_status = exit_code
+        .or_else(|| signals::terminated().map(|sig| 128 + sig))
+        .unwrap_or(if input_failed { 1 } else { 0 });
+    stamper.finish(exit_code, exit_status)?;
+    
+    if exit_status != 0 {
+        std::process::exit(exit_status);
     }
     Ok(())
 }
\ No newline at end of file
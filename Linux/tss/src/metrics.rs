@@ -0,0 +1,149 @@
+use crate::level::Level;
+use crate::sink::{Record, Sink};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Default)]
+struct Counters {
+    lines: u64,
+    bytes: u64,
+    levels: [u64; Level::ALL.len()],
+    max_gap: Duration,
+    last_line: Option<Instant>,
+    // Lines and time at the previous write, for the per-interval rate
+    written_lines: u64,
+    written_at: Option<Instant>,
+}
+
+/// Keeps a Prometheus textfile up to date for node_exporter's textfile
+/// collector. The file is rewritten on a timer rather than per line, so it
+/// keeps reporting the time since the last line while the stream is quiet.
+pub struct MetricsSink {
+    counters: Arc<Mutex<Counters>>,
+    stop: Option<Sender<()>>,
+    writer: Option<JoinHandle<()>>,
+    textfile: Arc<Textfile>,
+}
+
+struct Textfile {
+    path: PathBuf,
+    start: SystemTime,
+    started: Instant,
+}
+
+impl MetricsSink {
+    pub fn new(path: &str, interval: Duration) -> io::Result<Self> {
+        let counters = Arc::new(Mutex::new(Counters::default()));
+        let textfile = Arc::new(Textfile {
+            path: PathBuf::from(path),
+            start: SystemTime::now(),
+            started: Instant::now(),
+        });
+        // Fail early on an unwritable path instead of on the first tick
+        textfile.write(&mut counters.lock().unwrap())?;
+
+        let (stop, stopped) = mpsc::channel();
+        let writer = {
+            let counters = Arc::clone(&counters);
+            let textfile = Arc::clone(&textfile);
+            thread::spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    if let Err(e) = textfile.write(&mut counters.lock().unwrap()) {
+                        eprintln!("tss: metrics: {}: {}", textfile.path.display(), e);
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            counters,
+            stop: Some(stop),
+            writer: Some(writer),
+            textfile,
+        })
+    }
+}
+
+impl Sink for MetricsSink {
+    fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        let mut counters = self.counters.lock().unwrap();
+        for line in &record.lines {
+            counters.lines += 1;
+            counters.bytes += line.len() as u64 + 1;
+        }
+        if let Some(level) = Level::detect(&record.lines[0]) {
+            counters.levels[level as usize] += 1;
+        }
+        if counters.last_line.is_some() {
+            counters.max_gap = counters.max_gap.max(record.delta);
+        }
+        counters.last_line = Some(Instant::now());
+        Ok(())
+    }
+
+    fn finish(&mut self, _end: SystemTime, _exit_code: Option<i32>) -> io::Result<()> {
+        self.stop.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+        // Final snapshot; the rate covers the part interval since the last tick
+        self.textfile.write(&mut self.counters.lock().unwrap())
+    }
+}
+
+impl Textfile {
+    /// Render the counters and atomically replace the textfile. The rate
+    /// is computed from the lines seen since the previous write.
+    fn write(&self, counters: &mut Counters) -> io::Result<()> {
+        let now = Instant::now();
+        let since_last = (now - counters.last_line.unwrap_or(self.started)).as_secs_f64();
+        let interval = now - counters.written_at.unwrap_or(self.started);
+        let rate = (counters.lines - counters.written_lines) as f64 / interval.as_secs_f64().max(f64::EPSILON);
+        let average = counters.lines as f64 / (now - self.started).as_secs_f64().max(f64::EPSILON);
+        counters.written_lines = counters.lines;
+        counters.written_at = Some(now);
+        let start = self
+            .start
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        let mut out = String::with_capacity(1024);
+        let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        };
+        metric("tss_lines_total", "counter", "Lines read.", counters.lines as f64);
+        metric("tss_bytes_total", "counter", "Bytes read, including newlines.", counters.bytes as f64);
+        metric("tss_lines_per_second", "gauge", "Lines per second over the last interval.", rate);
+        metric("tss_lines_per_second_avg", "gauge", "Lines per second since the start.", average);
+        metric("tss_max_gap_seconds", "gauge", "Longest time between two lines.", counters.max_gap.as_secs_f64());
+        metric("tss_seconds_since_last_line", "gauge", "Time since the last line was read.", since_last);
+        metric("tss_start_time_seconds", "gauge", "Start time since the Unix epoch.", start);
+
+        let _ = writeln!(out, "# HELP tss_level_lines_total Lines by detected log level.");
+        let _ = writeln!(out, "# TYPE tss_level_lines_total counter");
+        for level in Level::ALL {
+            let _ = writeln!(
+                out,
+                "tss_level_lines_total{{level=\"{}\"}} {}",
+                level.as_str(),
+                counters.levels[level as usize]
+            );
+        }
+
+        // Write next to the target and rename over it, so the collector never
+        // reads a half-written file
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(format!(".{}.tmp", std::process::id()));
+        fs::write(&tmp, out)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
@@ -0,0 +1,333 @@
+use crate::latency::format_seconds;
+use crate::push_json_record;
+use crate::sink::{Record, Sink};
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+// Rendered records queued for sending
+const QUEUE_CAPACITY: usize = 8192;
+// Records written to a stream socket in one go
+const BATCH_BYTES: usize = 64 * 1024;
+// Largest payload of a UDP datagram
+const MAX_DATAGRAM: usize = 65507;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+// How long queued records may wait for a connection once input has ended
+const FINISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What --sink does when its queue is full
+#[derive(Clone, Copy, PartialEq)]
+pub enum Overflow {
+    Drop,
+    Block,
+}
+
+impl Overflow {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "drop" => Some(Overflow::Drop),
+            "block" => Some(Overflow::Block),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Target {
+    Tcp(String),
+    Udp(String),
+    Unix(PathBuf),
+}
+
+impl Target {
+    /// Parse tcp://HOST:PORT, udp://HOST:PORT or unix:///PATH. Host names
+    /// are resolved on every connect, so they may change while tss runs.
+    fn parse(url: &str) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", msg, url));
+        if let Some(path) = url.strip_prefix("unix://") {
+            if path.is_empty() {
+                return Err(invalid("missing socket path"));
+            }
+            return Ok(Target::Unix(PathBuf::from(path)));
+        }
+        let (address, udp) = match (url.strip_prefix("tcp://"), url.strip_prefix("udp://")) {
+            (Some(address), _) => (address, false),
+            (_, Some(address)) => (address, true),
+            _ => return Err(invalid("expected tcp://, udp:// or unix://")),
+        };
+        match address.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+            _ => return Err(invalid("expected HOST:PORT")),
+        }
+        let address = address.to_string();
+        Ok(if udp { Target::Udp(address) } else { Target::Tcp(address) })
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        match self {
+            Target::Tcp(address) => {
+                let mut last = io::Error::new(io::ErrorKind::NotFound, "host not found");
+                for addr in address.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&addr, IO_TIMEOUT) {
+                        Ok(stream) => {
+                            stream.set_write_timeout(Some(IO_TIMEOUT))?;
+                            return Ok(Connection::Tcp(stream));
+                        }
+                        Err(e) => last = e,
+                    }
+                }
+                Err(last)
+            }
+            Target::Udp(address) => {
+                let addr = address
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host not found"))?;
+                let local = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(addr)?;
+                Ok(Connection::Udp(socket))
+            }
+            Target::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_write_timeout(Some(IO_TIMEOUT))?;
+                Ok(Connection::Unix(stream))
+            }
+        }
+    }
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+    Unix(UnixStream),
+}
+
+impl Connection {
+    /// Send `messages`, one datagram each over UDP and back to back on a
+    /// stream. On failure the ones not yet sent stay in `messages`.
+    fn send(&mut self, messages: &mut Vec<Vec<u8>>) -> io::Result<()> {
+        match self {
+            Connection::Udp(socket) => {
+                while let Some(message) = messages.first() {
+                    socket.send(message)?;
+                    messages.remove(0);
+                }
+                Ok(())
+            }
+            Connection::Tcp(stream) => write_stream(stream, messages),
+            Connection::Unix(stream) => write_stream(stream, messages),
+        }
+    }
+}
+
+/// A partly written batch is sent again in full after reconnecting, so the
+/// collector may see a line twice but never loses the rest of one
+fn write_stream(stream: &mut impl Write, messages: &mut Vec<Vec<u8>>) -> io::Result<()> {
+    stream.write_all(&messages.concat())?;
+    messages.clear();
+    Ok(())
+}
+
+/// Sends every record as a line of text or JSON to a TCP, UDP or unix
+/// socket, for log shippers such as fluent-bit or vector. Records are
+/// rendered here and queued to a background thread that owns the
+/// connection and reconnects with backoff, so a slow or missing collector
+/// only fills the queue; what happens then is up to `Overflow`.
+pub struct NetSink {
+    url: String,
+    json: bool,
+    separator: String,
+    // Show the input or stream name, as --json does with "source"
+    labels: bool,
+    overflow: Overflow,
+    tx: Option<SyncSender<Vec<u8>>>,
+    worker: Option<JoinHandle<()>>,
+    closing: Arc<AtomicBool>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl NetSink {
+    pub fn new(url: &str, json: bool, separator: &str, labels: bool, overflow: Overflow) -> io::Result<Self> {
+        let target = Target::parse(url)?;
+        let (tx, rx) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let closing = Arc::new(AtomicBool::new(false));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let sender = Sender {
+            url: url.to_string(),
+            datagrams: matches!(target, Target::Udp(_)),
+            target,
+            closing: Arc::clone(&closing),
+            dropped: Arc::clone(&dropped),
+        };
+        let worker = thread::spawn(move || sender.run(rx));
+        Ok(Self {
+            url: url.to_string(),
+            json,
+            separator: separator.to_string(),
+            labels,
+            overflow,
+            tx: Some(tx),
+            worker: Some(worker),
+            closing,
+            dropped,
+        })
+    }
+
+    fn render(&self, record: &Record) -> Vec<u8> {
+        let mut out = Vec::with_capacity(128);
+        if self.json {
+            // Datagrams and shippers may reorder lines; "seq" restores order
+            push_json_record(&mut out, record, None, self.labels, true);
+            return out;
+        }
+        // Laid out like the terminal: upstream chain, timestamps, label
+        if let Some(ref upstream) = record.upstream {
+            out.extend_from_slice(upstream.prefix.as_bytes());
+            out.extend_from_slice(self.separator.as_bytes());
+        }
+        out.extend_from_slice(record.timestamp.as_bytes());
+        if let Some(ref completed) = record.completed {
+            out.extend_from_slice(self.separator.as_bytes());
+            out.extend_from_slice(completed.as_bytes());
+        }
+        if let Some(ref upstream) = record.upstream {
+            out.extend_from_slice(self.separator.as_bytes());
+            out.extend_from_slice(format_seconds(upstream.latency_ns).as_bytes());
+        }
+        out.extend_from_slice(self.separator.as_bytes());
+        if self.labels {
+            out.extend_from_slice(format!("[{}] ", record.stream).as_bytes());
+        }
+        out.extend_from_slice(record.text().as_bytes());
+        out.push(b'\n');
+        out
+    }
+}
+
+impl Sink for NetSink {
+    fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        let message = self.render(record);
+        let Some(ref tx) = self.tx else {
+            return Ok(());
+        };
+        let sent = match self.overflow {
+            Overflow::Drop => match tx.try_send(message) {
+                Err(TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                }
+                Err(TrySendError::Disconnected(_)) => Err(()),
+                Ok(()) => Ok(()),
+            },
+            Overflow::Block => tx.send(message).map_err(|_| ()),
+        };
+        sent.map_err(|()| io::Error::other(format!("sink {}: send thread exited", self.url)))
+    }
+
+    fn finish(&mut self, _end: SystemTime, _exit_code: Option<i32>) -> io::Result<()> {
+        // Closing the queue makes the worker send what is left and exit,
+        // giving up if the collector stays unreachable
+        self.closing.store(true, Ordering::Release);
+        self.tx.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            eprintln!("tss: sink {}: dropped {} records", self.url, dropped);
+        }
+        Ok(())
+    }
+}
+
+/// The background half of a NetSink
+struct Sender {
+    url: String,
+    target: Target,
+    datagrams: bool,
+    closing: Arc<AtomicBool>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Sender {
+    fn run(&self, rx: Receiver<Vec<u8>>) {
+        let mut connection: Option<Connection> = None;
+        let mut backoff = INITIAL_BACKOFF;
+        // Set while the collector is unreachable, so the outage is
+        // reported once rather than on every attempt
+        let mut down = false;
+        let mut give_up_at = None;
+        let mut batch = Vec::new();
+        loop {
+            if batch.is_empty() {
+                match rx.recv() {
+                    Ok(message) => batch.push(message),
+                    Err(_) => return,
+                }
+            }
+            let mut size: usize = batch.iter().map(Vec::len).sum();
+            while !self.datagrams && size < BATCH_BYTES {
+                match rx.try_recv() {
+                    Ok(message) => {
+                        size += message.len();
+                        batch.push(message);
+                    }
+                    Err(TryRecvError::Empty | TryRecvError::Disconnected) => break,
+                }
+            }
+            if self.datagrams {
+                let before = batch.len();
+                batch.retain(|message| message.len() <= MAX_DATAGRAM);
+                self.dropped.fetch_add((before - batch.len()) as u64, Ordering::Relaxed);
+            }
+
+            let result = match connection {
+                Some(ref mut connection) => connection.send(&mut batch),
+                None => self.target.connect().and_then(|mut connected| {
+                    let result = connected.send(&mut batch);
+                    connection = Some(connected);
+                    result
+                }),
+            };
+            match result {
+                Ok(()) => {
+                    if down {
+                        eprintln!("tss: sink {}: connected", self.url);
+                        down = false;
+                    }
+                    backoff = INITIAL_BACKOFF;
+                    give_up_at = None;
+                }
+                Err(e) => {
+                    connection = None;
+                    if !down {
+                        eprintln!("tss: sink {}: {}; retrying", self.url, e);
+                        down = true;
+                    }
+                    let mut wait = backoff;
+                    if self.closing.load(Ordering::Acquire) {
+                        let deadline = *give_up_at.get_or_insert_with(|| Instant::now() + FINISH_TIMEOUT);
+                        let left = deadline.saturating_duration_since(Instant::now());
+                        if left.is_zero() {
+                            let lost = batch.len() + rx.try_iter().count();
+                            self.dropped.fetch_add(lost as u64, Ordering::Relaxed);
+                            return;
+                        }
+                        wait = wait.min(left);
+                    }
+                    thread::sleep(wait);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+}
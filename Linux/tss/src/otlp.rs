@@ -0,0 +1,585 @@
+use crate::level::Level;
+use crate::push_json_string;
+use crate::sink::{epoch_nanos, Record, RunInfo, Sink, Stream};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+// Records queued for export before new ones are dropped
+const QUEUE_CAPACITY: usize = 8192;
+const BATCH_SIZE: usize = 512;
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Json,
+    Protobuf,
+}
+
+pub struct OtlpConfig {
+    pub endpoint: String,
+    pub protocol: Protocol,
+    pub service_name: String,
+    pub attributes: Vec<(String, String)>,
+}
+
+/// One log record, owned so it can cross to the export thread
+struct Entry {
+    time_ns: u64,
+    observed_ns: u64,
+    severity: Option<Level>,
+    body: String,
+    stream: Stream,
+    seq: u64,
+}
+
+/// Exports every record as an OTLP/HTTP log record. Records are queued to a
+/// background thread that sends them in batches, so a slow or unreachable
+/// collector never holds up stdout; when the queue is full records are
+/// dropped and counted.
+pub struct OtlpSink {
+    tx: Option<SyncSender<Entry>>,
+    worker: Option<JoinHandle<()>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl OtlpSink {
+    pub fn new(config: OtlpConfig, run: &RunInfo) -> io::Result<Self> {
+        let endpoint = Endpoint::parse(&config.endpoint)?;
+
+        let mut resource = vec![
+            ("service.name".to_string(), config.service_name.clone()),
+            ("host.name".to_string(), run.host.clone()),
+            ("tss.run_id".to_string(), run.id.clone()),
+        ];
+        if let Some(ref command) = run.command {
+            resource.push(("process.command_line".to_string(), command.clone()));
+        }
+        resource.extend(config.attributes.iter().cloned());
+
+        let (tx, rx) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let exporter = Exporter {
+            endpoint,
+            protocol: config.protocol,
+            resource,
+        };
+        let worker = thread::spawn(move || exporter.run(rx));
+
+        Ok(Self {
+            tx: Some(tx),
+            worker: Some(worker),
+            dropped: Arc::new(AtomicU64::new(0)),
+        })
+    }
+}
+
+impl Sink for OtlpSink {
+    fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        let body = record.text();
+        let entry = Entry {
+            time_ns: record.epoch_nanos() as u64,
+            observed_ns: epoch_nanos(SystemTime::now()) as u64,
+            severity: Level::detect(&body),
+            body,
+            stream: record.stream.clone(),
+            seq: record.seq,
+        };
+        if let Some(ref tx) = self.tx {
+            match tx.try_send(entry) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    return Err(io::Error::other("otlp: export thread exited"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, _end: SystemTime, _exit_code: Option<i32>) -> io::Result<()> {
+        // Closing the queue makes the worker export what is left and exit
+        self.tx.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            eprintln!("tss: otlp: dropped {} records, export queue was full", dropped);
+        }
+        Ok(())
+    }
+}
+
+struct Exporter {
+    endpoint: Endpoint,
+    protocol: Protocol,
+    resource: Vec<(String, String)>,
+}
+
+impl Exporter {
+    fn run(&self, rx: Receiver<Entry>) {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut deadline = Instant::now() + BATCH_INTERVAL;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let closed = match rx.recv_timeout(timeout) {
+                Ok(entry) => {
+                    if batch.is_empty() {
+                        deadline = Instant::now() + BATCH_INTERVAL;
+                    }
+                    batch.push(entry);
+                    if batch.len() < BATCH_SIZE && Instant::now() < deadline {
+                        continue;
+                    }
+                    false
+                }
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => true,
+            };
+            if !batch.is_empty() {
+                self.export(&batch);
+                batch.clear();
+            }
+            if closed {
+                return;
+            }
+            deadline = Instant::now() + BATCH_INTERVAL;
+        }
+    }
+
+    /// Send one batch, retrying connection failures and throttling
+    /// responses with exponential backoff
+    fn export(&self, batch: &[Entry]) {
+        let (body, content_type) = match self.protocol {
+            Protocol::Json => (self.encode_json(batch), "application/json"),
+            Protocol::Protobuf => (self.encode_protobuf(batch), "application/x-protobuf"),
+        };
+
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 1..=MAX_ATTEMPTS {
+            match self.endpoint.post(content_type, &body) {
+                Ok(status) if (200..300).contains(&status) => return,
+                Ok(status) if !matches!(status, 429 | 502 | 503 | 504) => {
+                    eprintln!("tss: otlp: collector rejected {} records with HTTP {}", batch.len(), status);
+                    return;
+                }
+                Ok(status) if attempt == MAX_ATTEMPTS => {
+                    eprintln!("tss: otlp: dropped {} records after HTTP {}", batch.len(), status);
+                }
+                Err(e) if attempt == MAX_ATTEMPTS => {
+                    eprintln!("tss: otlp: dropped {} records: {}", batch.len(), e);
+                }
+                _ => {
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+
+    fn encode_json(&self, batch: &[Entry]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(256 * batch.len());
+        out.extend_from_slice(b"{\"resourceLogs\":[{\"resource\":{\"attributes\":[");
+        for (i, (key, value)) in self.resource.iter().enumerate() {
+            if i > 0 {
+                out.push(b',');
+            }
+            json_string_attribute(&mut out, key, value);
+        }
+        out.extend_from_slice(b"]},\"scopeLogs\":[{\"scope\":{\"name\":\"tss\",\"version\":");
+        push_json_string(&mut out, env!("CARGO_PKG_VERSION"));
+        out.extend_from_slice(b"},\"logRecords\":[");
+        for (i, entry) in batch.iter().enumerate() {
+            if i > 0 {
+                out.push(b',');
+            }
+            out.extend_from_slice(
+                format!(
+                    "{{\"timeUnixNano\":\"{}\",\"observedTimeUnixNano\":\"{}\",\"severityNumber\":{}",
+                    entry.time_ns,
+                    entry.observed_ns,
+                    severity_number(entry.severity)
+                )
+                .as_bytes(),
+            );
+            if let Some(level) = entry.severity {
+                out.extend_from_slice(b",\"severityText\":");
+                push_json_string(&mut out, &level.as_str().to_ascii_uppercase());
+            }
+            out.extend_from_slice(b",\"body\":{\"stringValue\":");
+            push_json_string(&mut out, &entry.body);
+            out.extend_from_slice(b"},\"attributes\":[");
+            json_string_attribute(&mut out, "log.iostream", entry.stream.name());
+            out.extend_from_slice(
+                format!(",{{\"key\":\"tss.seq\",\"value\":{{\"intValue\":\"{}\"}}}}]}}", entry.seq)
+                    .as_bytes(),
+            );
+        }
+        out.extend_from_slice(b"]}]}]}");
+        out
+    }
+
+    fn encode_protobuf(&self, batch: &[Entry]) -> Vec<u8> {
+        // Resource { repeated KeyValue attributes = 1 }
+        let mut resource = Vec::new();
+        for (key, value) in &self.resource {
+            pb_bytes(&mut resource, 1, &pb_string_key_value(key, value));
+        }
+
+        // InstrumentationScope { string name = 1; string version = 2 }
+        let mut scope = Vec::new();
+        pb_bytes(&mut scope, 1, b"tss");
+        pb_bytes(&mut scope, 2, env!("CARGO_PKG_VERSION").as_bytes());
+
+        // ScopeLogs { InstrumentationScope scope = 1; repeated LogRecord log_records = 2 }
+        let mut scope_logs = Vec::new();
+        pb_bytes(&mut scope_logs, 1, &scope);
+        for entry in batch {
+            let mut log = Vec::new();
+            pb_fixed64(&mut log, 1, entry.time_ns);
+            pb_varint_field(&mut log, 2, severity_number(entry.severity));
+            if let Some(level) = entry.severity {
+                pb_bytes(&mut log, 3, level.as_str().to_ascii_uppercase().as_bytes());
+            }
+            let mut body = Vec::new();
+            pb_bytes(&mut body, 1, entry.body.as_bytes());
+            pb_bytes(&mut log, 5, &body);
+            pb_bytes(&mut log, 6, &pb_string_key_value("log.iostream", entry.stream.name()));
+            let mut seq = Vec::new();
+            pb_varint_field(&mut seq, 3, entry.seq);
+            let mut seq_kv = Vec::new();
+            pb_bytes(&mut seq_kv, 1, b"tss.seq");
+            pb_bytes(&mut seq_kv, 2, &seq);
+            pb_bytes(&mut log, 6, &seq_kv);
+            pb_fixed64(&mut log, 11, entry.observed_ns);
+            pb_bytes(&mut scope_logs, 2, &log);
+        }
+
+        // ResourceLogs { Resource resource = 1; repeated ScopeLogs scope_logs = 2 }
+        let mut resource_logs = Vec::new();
+        pb_bytes(&mut resource_logs, 1, &resource);
+        pb_bytes(&mut resource_logs, 2, &scope_logs);
+
+        // ExportLogsServiceRequest { repeated ResourceLogs resource_logs = 1 }
+        let mut request = Vec::new();
+        pb_bytes(&mut request, 1, &resource_logs);
+        request
+    }
+}
+
+/// OTLP SeverityNumber for a detected level, 0 (unspecified) otherwise
+fn severity_number(level: Option<Level>) -> u64 {
+    match level {
+        None => 0,
+        Some(Level::Trace) => 1,
+        Some(Level::Debug) => 5,
+        Some(Level::Info) => 9,
+        Some(Level::Warn) => 13,
+        Some(Level::Error) => 17,
+        Some(Level::Fatal) => 21,
+    }
+}
+
+fn json_string_attribute(out: &mut Vec<u8>, key: &str, value: &str) {
+    out.extend_from_slice(b"{\"key\":");
+    push_json_string(out, key);
+    out.extend_from_slice(b",\"value\":{\"stringValue\":");
+    push_json_string(out, value);
+    out.extend_from_slice(b"}}");
+}
+
+fn pb_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn pb_varint_field(out: &mut Vec<u8>, field: u64, value: u64) {
+    pb_varint(out, field << 3);
+    pb_varint(out, value);
+}
+
+fn pb_fixed64(out: &mut Vec<u8>, field: u64, value: u64) {
+    pb_varint(out, (field << 3) | 1);
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn pb_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    pb_varint(out, (field << 3) | 2);
+    pb_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// KeyValue { string key = 1; AnyValue value = 2 } with a string_value
+fn pb_string_key_value(key: &str, value: &str) -> Vec<u8> {
+    let mut any = Vec::new();
+    pb_bytes(&mut any, 1, value.as_bytes());
+    let mut kv = Vec::new();
+    pb_bytes(&mut kv, 1, key.as_bytes());
+    pb_bytes(&mut kv, 2, &any);
+    kv
+}
+
+/// A plain http:// collector URL
+struct Endpoint {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Endpoint {
+    fn parse(url: &str) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", msg, url));
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| invalid("only http:// endpoints are supported"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/v1/logs"),
+        };
+        // An IPv6 address is bracketed, as in http://[::1]:4318
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, rest) = bracketed
+                    .split_once(']')
+                    .ok_or_else(|| invalid("missing ] after IPv6 address"))?;
+                match rest {
+                    "" => (host, None),
+                    _ => (host, Some(rest.strip_prefix(':').ok_or_else(|| invalid("invalid port"))?)),
+                }
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid("invalid port"))?,
+            None => 4318,
+        };
+        if host.is_empty() {
+            return Err(invalid("missing host"));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// POST `body` and return the HTTP status code
+    fn post(&self, content_type: &str, body: &[u8]) -> io::Result<u16> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host not found"))?;
+        let mut stream = TcpStream::connect_timeout(&addr, IO_TIMEOUT)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        let header = format!(
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            host,
+            self.port,
+            content_type,
+            body.len()
+        );
+        stream.write_all(header.as_bytes())?;
+        stream.write_all(body)?;
+
+        // Only the status line matters; "HTTP/1.1 200 OK"
+        let mut response = [0u8; 64];
+        let mut len = 0;
+        while len < response.len() {
+            let n = stream.read(&mut response[len..])?;
+            if n == 0 {
+                break;
+            }
+            len += n;
+            if response[..len].contains(&b'\n') {
+                break;
+            }
+        }
+        std::str::from_utf8(&response[..len])
+            .ok()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The fields of a protobuf message, with length-delimited values as
+    /// bytes and the rest as integers
+    fn pb_fields(mut message: &[u8]) -> Vec<(u64, Result<u64, &[u8]>)> {
+        fn varint(bytes: &mut &[u8]) -> u64 {
+            let mut value = 0;
+            for shift in (0..64).step_by(7) {
+                let byte = bytes[0];
+                *bytes = &bytes[1..];
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte < 0x80 {
+                    break;
+                }
+            }
+            value
+        }
+        let mut fields = Vec::new();
+        while !message.is_empty() {
+            let key = varint(&mut message);
+            let value = match key & 7 {
+                0 => Ok(varint(&mut message)),
+                1 => {
+                    let (fixed, rest) = message.split_at(8);
+                    message = rest;
+                    Ok(u64::from_le_bytes(fixed.try_into().unwrap()))
+                }
+                2 => {
+                    let len = varint(&mut message) as usize;
+                    let (bytes, rest) = message.split_at(len);
+                    message = rest;
+                    Err(bytes)
+                }
+                wire => panic!("unexpected wire type {}", wire),
+            };
+            fields.push((key >> 3, value));
+        }
+        fields
+    }
+
+    fn field(message: &[u8], number: u64) -> &[u8] {
+        pb_fields(message)
+            .into_iter()
+            .find_map(|(n, value)| (n == number).then_some(value).and_then(Result::err))
+            .unwrap_or_else(|| panic!("no field {}", number))
+    }
+
+    fn exporter(protocol: Protocol) -> Exporter {
+        Exporter {
+            endpoint: Endpoint::parse("http://localhost").unwrap(),
+            protocol,
+            resource: vec![("service.name".to_string(), "tss".to_string())],
+        }
+    }
+
+    fn entry() -> Entry {
+        Entry {
+            time_ns: 1_792_385_859_000_000_000,
+            observed_ns: 1_792_385_859_000_000_001,
+            severity: Some(Level::Warn),
+            body: "WARN disk \"almost\" full".to_string(),
+            stream: Stream::Stderr,
+            seq: 300,
+        }
+    }
+
+    #[test]
+    fn varints_use_seven_bit_groups() {
+        let mut out = Vec::new();
+        pb_varint(&mut out, 1);
+        pb_varint(&mut out, 300);
+        pb_varint(&mut out, u64::MAX);
+        assert_eq!(out[..3], [0x01, 0xac, 0x02]);
+        assert_eq!(out.len(), 3 + 10);
+        assert_eq!(out[12], 0x01);
+    }
+
+    #[test]
+    fn fields_carry_their_wire_type() {
+        let mut out = Vec::new();
+        pb_varint_field(&mut out, 2, 9);
+        pb_fixed64(&mut out, 1, 7);
+        pb_bytes(&mut out, 5, b"hi");
+        assert_eq!(out, [0x10, 9, 0x09, 7, 0, 0, 0, 0, 0, 0, 0, 0x2a, 2, b'h', b'i']);
+    }
+
+    #[test]
+    fn string_key_values_nest_an_any_value() {
+        let kv = pb_string_key_value("log.iostream", "stderr");
+        assert_eq!(field(&kv, 1), b"log.iostream");
+        assert_eq!(field(field(&kv, 2), 1), b"stderr");
+    }
+
+    #[test]
+    fn protobuf_requests_follow_the_otlp_schema() {
+        let request = exporter(Protocol::Protobuf).encode_protobuf(&[entry()]);
+        let resource_logs = field(&request, 1);
+        let attribute = field(field(resource_logs, 1), 1);
+        assert_eq!(field(attribute, 1), b"service.name");
+
+        let scope_logs = field(resource_logs, 2);
+        assert_eq!(field(field(scope_logs, 1), 1), b"tss");
+        let log = pb_fields(field(scope_logs, 2));
+        assert!(log.contains(&(1, Ok(1_792_385_859_000_000_000))));
+        assert!(log.contains(&(2, Ok(13))));
+        assert!(log.contains(&(3, Err(&b"WARN"[..]))));
+        assert!(log.contains(&(11, Ok(1_792_385_859_000_000_001))));
+        let body = field(field(scope_logs, 2), 5);
+        assert_eq!(field(body, 1), b"WARN disk \"almost\" full");
+
+        let attributes: Vec<_> = log.iter().filter(|(n, _)| *n == 6).collect();
+        assert_eq!(attributes.len(), 2);
+        let seq = attributes[1].1.unwrap_err();
+        assert_eq!(field(seq, 1), b"tss.seq");
+        assert!(pb_fields(field(seq, 2)).contains(&(3, Ok(300))));
+    }
+
+    #[test]
+    fn json_requests_escape_the_body() {
+        let request = exporter(Protocol::Json).encode_json(&[entry()]);
+        let request = String::from_utf8(request).unwrap();
+        assert!(request.starts_with(
+            "{\"resourceLogs\":[{\"resource\":{\"attributes\":[{\"key\":\"service.name\",\"value\":{\"stringValue\":\"tss\"}}]}"
+        ));
+        assert!(request.contains(
+            "{\"timeUnixNano\":\"1792385859000000000\",\"observedTimeUnixNano\":\"1792385859000000001\",\"severityNumber\":13,\"severityText\":\"WARN\""
+        ));
+        assert!(request.contains("\"body\":{\"stringValue\":\"WARN disk \\\"almost\\\" full\"}"));
+        assert!(request.contains("{\"key\":\"log.iostream\",\"value\":{\"stringValue\":\"stderr\"}}"));
+        assert!(request.ends_with("{\"key\":\"tss.seq\",\"value\":{\"intValue\":\"300\"}}]}]}]}]}"));
+    }
+
+    #[test]
+    fn endpoints_default_port_and_path() {
+        let endpoint = Endpoint::parse("http://collector").unwrap();
+        assert_eq!((endpoint.host.as_str(), endpoint.port, endpoint.path.as_str()), ("collector", 4318, "/v1/logs"));
+        let endpoint = Endpoint::parse("http://collector:9000/otlp/logs").unwrap();
+        assert_eq!((endpoint.host.as_str(), endpoint.port, endpoint.path.as_str()), ("collector", 9000, "/otlp/logs"));
+    }
+
+    #[test]
+    fn endpoints_accept_bracketed_ipv6() {
+        let endpoint = Endpoint::parse("http://[::1]:4318").unwrap();
+        assert_eq!((endpoint.host.as_str(), endpoint.port), ("::1", 4318));
+        let endpoint = Endpoint::parse("http://[fe80::1]/v1/logs").unwrap();
+        assert_eq!((endpoint.host.as_str(), endpoint.port), ("fe80::1", 4318));
+    }
+
+    #[test]
+    fn endpoints_reject_bad_urls() {
+        for url in ["https://collector", "http://:4318", "http://collector:port", "http://[::1", "http://[::1]x"] {
+            assert!(Endpoint::parse(url).is_err(), "{}", url);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{Config, FormatType};
+use chrono::format::{parse_and_remainder, Parsed, StrftimeItems};
+
+pub const NANOS_PER_DAY: i128 = 86_400 * 1_000_000_000;
+
+/// Parses the timestamp prefix that a writer with the same options would
+/// have produced, yielding a position on a nanosecond timeline
+pub struct PrefixParser {
+    format: String,
+    kind: Kind,
+    separator: String,
+    // Timeline position of the previous line, for delta sums and midnight wraps
+    last: Option<i128>,
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    // Date and/or time of day via strftime
+    Clock,
+    // Integer count of this many nanoseconds since the epoch
+    Epoch(i128),
+    // Seconds since the previous line
+    Delta,
+}
+
+impl PrefixParser {
+    pub fn new(config: &Config) -> Self {
+        let (kind, format) = match FormatType::from_config(config) {
+            FormatType::CommonISO => (Kind::Clock, "%Y-%m-%d %H:%M:%S".to_string()),
+            FormatType::CommonISOMs | FormatType::CommonISOUs | FormatType::CommonISONs => {
+                (Kind::Clock, "%Y-%m-%d %H:%M:%S%.f".to_string())
+            }
+            FormatType::ISO8601 => (Kind::Clock, "%Y-%m-%dT%H:%M:%S%.3f%z".to_string()),
+            FormatType::Epoch => (Kind::Epoch(1_000_000_000), String::new()),
+            FormatType::EpochUs => (Kind::Epoch(1_000), String::new()),
+            FormatType::EpochNs => (Kind::Epoch(1), String::new()),
+            FormatType::Delta => (Kind::Delta, String::new()),
+            FormatType::Custom(fmt) => (Kind::Clock, fmt),
+        };
+        Self {
+            format,
+            kind,
+            separator: config.separator.clone(),
+            last: None,
+        }
+    }
+
+    /// Split `line` into its timeline position and the original text.
+    /// Returns None for lines without a prefix, such as continuation lines.
+    pub fn parse<'a>(&mut self, line: &'a str) -> Option<(i128, &'a str)> {
+        let (position, text) = self.peek(line)?;
+        self.commit(position);
+        Some((position, text))
+    }
+
+    /// Like `parse`, but leaves the previous position alone, for text that
+    /// may turn out not to be a prefix after all
+    pub fn peek<'a>(&self, line: &'a str) -> Option<(i128, &'a str)> {
+        let line = skip_sgr(line);
+        let (position, rest) = match self.kind {
+            Kind::Clock => self.parse_clock(line)?,
+            Kind::Epoch(unit) => {
+                let end = line.find(|c: char| !c.is_ascii_digit()).unwrap_or(line.len());
+                (line[..end].parse::<i128>().ok()? * unit, &line[end..])
+            }
+            Kind::Delta => {
+                let end = line
+                    .find(|c: char| !c.is_ascii_digit() && c != '.')
+                    .unwrap_or(line.len());
+                let (secs, micros) = line[..end].split_once('.')?;
+                let delta = secs.parse::<i128>().ok()? * 1_000_000_000
+                    + micros.parse::<i128>().ok()? * 1_000;
+                (self.last.unwrap_or(0) + delta, &line[end..])
+            }
+        };
+        let rest = skip_sgr(rest);
+        let text = rest.strip_prefix(self.separator.as_str()).unwrap_or(rest);
+        Some((position, text))
+    }
+
+    /// Make `position` the previous line's, as `parse` does
+    pub fn commit(&mut self, position: i128) {
+        self.last = Some(position);
+    }
+
+    fn parse_clock<'a>(&self, line: &'a str) -> Option<(i128, &'a str)> {
+        let mut parsed = Parsed::new();
+        let rest = parse_and_remainder(&mut parsed, line, StrftimeItems::new(&self.format)).ok()?;
+
+        let position = if let Ok(dt) = parsed.to_naive_datetime_with_offset(0) {
+            let offset = parsed.to_fixed_offset().map_or(0, |o| o.local_minus_utc());
+            let utc = dt.and_utc().timestamp_nanos_opt()? as i128;
+            utc - offset as i128 * 1_000_000_000
+        } else {
+            // Time of day only; assume a wrap past midnight when time goes back.
+            // Formats such as "%M:%S" leave out the larger units, which count
+            // as zero (setting a field that was parsed is a no-op error)
+            let _ = parsed.set_hour(0);
+            let _ = parsed.set_minute(0);
+            let _ = parsed.set_second(0);
+            let time = parsed.to_naive_time().ok()?;
+            let since_midnight = (time - chrono::NaiveTime::MIN).num_nanoseconds()? as i128;
+            let day = self.last.map_or(0, |last| last.div_euclid(NANOS_PER_DAY));
+            let mut position = day * NANOS_PER_DAY + since_midnight;
+            if self.last.is_some_and(|last| position < last) {
+                position += NANOS_PER_DAY;
+            }
+            position
+        };
+        Some((position, rest))
+    }
+}
+
+/// Skip leading SGR color sequences as written by --color
+pub fn skip_sgr(mut s: &str) -> &str {
+    while let Some(rest) = s.strip_prefix("\x1b[") {
+        match rest.find('m') {
+            Some(end) if rest[..end].bytes().all(|b| b.is_ascii_digit() || b == b';') => {
+                s = &rest[end + 1..];
+            }
+            _ => break,
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: i128 = 1_000_000_000;
+
+    fn parser_for(setup: impl FnOnce(&mut Config)) -> PrefixParser {
+        let mut config = Config::default();
+        setup(&mut config);
+        PrefixParser::new(&config)
+    }
+
+    #[test]
+    fn parses_the_default_format() {
+        let mut parser = parser_for(|_| {});
+        let (position, text) = parser.parse("2026-10-19 04:57:39 hello world").unwrap();
+        assert_eq!(position, 1_792_385_859 * SECOND);
+        assert_eq!(text, "hello world");
+    }
+
+    #[test]
+    fn parses_fractions_and_offsets() {
+        let mut parser = parser_for(|config| config.format = "%Y-%m-%d %H:%M:%S%.3f".to_string());
+        let (position, _) = parser.parse("2026-10-19 04:57:39.250 x").unwrap();
+        assert_eq!(position, 1_792_385_859 * SECOND + 250_000_000);
+
+        let mut parser = parser_for(|config| config.iso = true);
+        let (position, text) = parser.parse("2026-10-19T06:57:39.000+0200 x").unwrap();
+        assert_eq!(position, 1_792_385_859 * SECOND);
+        assert_eq!(text, "x");
+    }
+
+    #[test]
+    fn parses_epoch_units() {
+        let mut parser = parser_for(|config| config.since_epoch = true);
+        assert_eq!(parser.parse("1792385859 x"), Some((1_792_385_859 * SECOND, "x")));
+
+        let mut parser = parser_for(|config| {
+            config.since_epoch = true;
+            config.microseconds = true;
+        });
+        assert_eq!(parser.parse("1792385859000001 x"), Some((1_792_385_859 * SECOND + 1_000, "x")));
+    }
+
+    #[test]
+    fn sums_deltas() {
+        let mut parser = parser_for(|config| config.delta = true);
+        assert_eq!(parser.parse("0.500000 a"), Some((SECOND / 2, "a")));
+        assert_eq!(parser.parse("1.000001 b"), Some((3 * SECOND / 2 + 1_000, "b")));
+    }
+
+    #[test]
+    fn time_of_day_wraps_past_midnight() {
+        let mut parser = parser_for(|config| config.format = "%H:%M:%S".to_string());
+        let (before, _) = parser.parse("23:59:59 a").unwrap();
+        let (after, _) = parser.parse("00:00:01 b").unwrap();
+        assert_eq!(after - before, 2 * SECOND);
+    }
+
+    #[test]
+    fn peeking_leaves_the_day_alone() {
+        let mut parser = parser_for(|config| config.format = "%H:%M:%S".to_string());
+        let (first, _) = parser.parse("10:00:00 a").unwrap();
+        // A log line that happens to start with an earlier time
+        assert!(parser.peek("09:00:00 b").is_some());
+        let (second, _) = parser.parse("10:00:01 c").unwrap();
+        assert_eq!(second - first, SECOND);
+    }
+
+    #[test]
+    fn skips_colors_and_custom_separators() {
+        let mut parser = parser_for(|config| config.separator = " | ".to_string());
+        let (_, text) = parser.parse("\x1b[2m2026-10-19 04:57:39\x1b[0m | hello").unwrap();
+        assert_eq!(text, "hello");
+    }
+
+    #[test]
+    fn rejects_lines_without_a_prefix() {
+        let mut parser = parser_for(|_| {});
+        assert_eq!(parser.parse("  continued"), None);
+        assert_eq!(parser.parse(""), None);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::signals;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::ptr;
+use std::thread;
+
+// Signals relayed to the child while it runs under the pseudo-terminal
+pub const FORWARDED_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGWINCH,
+    libc::SIGINT,
+    libc::SIGTERM,
+    libc::SIGHUP,
+    libc::SIGQUIT,
+];
+
+pub struct PtyChild {
+    pub child: Child,
+    pub master: PtyReader,
+}
+
+/// Reads the master side of the pseudo-terminal. Linux reports EIO once
+/// every slave descriptor has been closed, which is treated as end of input.
+pub struct PtyReader {
+    file: File,
+}
+
+impl Read for PtyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.file.read(buf) {
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+/// Spawn `command` with its stdout and stderr attached to a new
+/// pseudo-terminal. Stdin is passed through unchanged.
+pub fn spawn(command: &[String]) -> io::Result<PtyChild> {
+    let mut master: RawFd = -1;
+    let mut slave: RawFd = -1;
+    let mut winsize = terminal_size();
+    let winsize_ptr = winsize
+        .as_mut()
+        .map_or(ptr::null_mut(), |ws| ws as *mut libc::winsize);
+
+    // SAFETY: both out-pointers are valid, termios is left at its default
+    let ret = unsafe {
+        libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), winsize_ptr)
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: openpty returned two freshly opened descriptors we now own
+    let master = unsafe { File::from_raw_fd(master) };
+    let slave = unsafe { File::from_raw_fd(slave) };
+    set_cloexec(master.as_raw_fd())?;
+    set_cloexec(slave.as_raw_fd())?;
+
+    // signals::install has blocked the forwarded signals in every thread, so
+    // the relay thread is the only one that ever sees them
+    let sigset = forwarded_sigset();
+
+    let mut cmd = Command::new(&command[0]);
+    cmd.args(&command[1..])
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+
+    // SAFETY: only async-signal-safe calls are made between fork and exec
+    unsafe {
+        cmd.pre_exec(|| {
+            // New session with the pty slave (fd 1) as controlling terminal
+            if libc::setsid() < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::ioctl(1, libc::TIOCSCTTY as _, 0) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    signals::unblock_in_child(&mut cmd);
+
+    let child = cmd.spawn()?;
+    // The slave copies held by `cmd` are closed here, so the master sees
+    // EIO as soon as the child and its descendants are done with it
+    drop(cmd);
+
+    let master_fd = master.as_raw_fd();
+    let pid = child.id() as libc::pid_t;
+    thread::spawn(move || relay_signals(sigset, master_fd, pid));
+
+    Ok(PtyChild {
+        child,
+        master: PtyReader { file: master },
+    })
+}
+
+/// Map a child's exit status to the code tss should exit with, using the
+/// shell convention of 128 + signal number for signal deaths.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|sig| 128 + sig))
+        .unwrap_or(1)
+}
+
+fn relay_signals(sigset: libc::sigset_t, master_fd: RawFd, pid: libc::pid_t) {
+    loop {
+        let mut sig: libc::c_int = 0;
+        // SAFETY: sigset and sig are valid for the duration of the call
+        if unsafe { libc::sigwait(&sigset, &mut sig) } != 0 {
+            continue;
+        }
+        if sig == libc::SIGWINCH {
+            if let Some(ws) = terminal_size() {
+                // SAFETY: master_fd stays open for the life of the process
+                unsafe { libc::ioctl(master_fd, libc::TIOCSWINSZ, &ws) };
+            }
+        } else {
+            // The child leads its own session; signal the whole group
+            // SAFETY: kill has no memory safety requirements
+            unsafe { libc::kill(-pid, sig) };
+        }
+    }
+}
+
+/// Window size of whichever standard stream is attached to a terminal.
+fn terminal_size() -> Option<libc::winsize> {
+    [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO]
+        .into_iter()
+        .find_map(|fd| {
+            // SAFETY: winsize is plain data and TIOCGWINSZ only writes into it
+            let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+            let ret = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut ws) };
+            (ret == 0 && ws.ws_col > 0).then_some(ws)
+        })
+}
+
+fn forwarded_sigset() -> libc::sigset_t {
+    // SAFETY: sigemptyset initialises the set before any sigaddset
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for sig in FORWARDED_SIGNALS {
+            libc::sigaddset(&mut set, sig);
+        }
+        set
+    }
+}
+
+fn set_cloexec(fd: RawFd) -> io::Result<()> {
+    // SAFETY: fcntl on a descriptor we own
+    let ret = unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use crate::banner;
+use crate::prefix::PrefixParser;
+use crate::Config;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct ReplayOptions {
+    pub input: String,
+    pub speed: f64,
+    pub max_gap: Option<Duration>,
+    pub seek: Duration,
+    pub keep_timestamps: bool,
+}
+
+/// Re-emit a timestamped log on stdout with its original line spacing
+pub fn run(config: &Config, opts: &ReplayOptions) -> io::Result<()> {
+    let input: Box<dyn io::Read> = if opts.input == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(&opts.input)?)
+    };
+    let reader = BufReader::with_capacity(128 * 1024, input);
+    let mut stdout = io::stdout().lock();
+    let mut parser = PrefixParser::new(config);
+
+    let mut schedule = Schedule {
+        first: None,
+        previous: None,
+        virtual_nanos: 0,
+        seek: opts.seek.as_nanos() as i128,
+        max_gap: opts.max_gap.map(|gap| gap.as_nanos() as i128),
+        speed: opts.speed,
+    };
+    let started = Instant::now();
+    // Lines without a prefix follow the previous line, including its seek
+    let mut skipping = schedule.seek > 0;
+    // Byte length of the last prefix, to strip --continuation pad padding
+    let mut prefix_len = 0;
+
+    for line_result in reader.lines() {
+        let line = line_result?;
+        // JSON banners have no "timestamp" and are skipped below
+        if banner::is_text_banner(&line) {
+            continue;
+        }
+
+        let (text, json_text);
+        if config.json {
+            let Some((timestamp, body)) = split_json(&line) else {
+                continue;
+            };
+            json_text = body;
+            let Some((position, _)) = parser.parse(&timestamp) else {
+                continue;
+            };
+            skipping = schedule.advance(position);
+            text = if opts.keep_timestamps { line.as_str() } else { json_text.as_str() };
+        } else if let Some((position, rest)) = parser.parse(&line) {
+            skipping = schedule.advance(position);
+            prefix_len = line.len() - rest.len();
+            text = if opts.keep_timestamps { line.as_str() } else { rest };
+        } else {
+            let padded = line.len() >= prefix_len
+                && line.as_bytes()[..prefix_len].iter().all(|&b| b == b' ' || b == b'\t');
+            text = if padded && !opts.keep_timestamps { &line[prefix_len..] } else { line.as_str() };
+        }
+
+        if skipping {
+            continue;
+        }
+        let target = started + schedule.due();
+        let now = Instant::now();
+        if target > now {
+            thread::sleep(target - now);
+        }
+        stdout.write_all(text.as_bytes())?;
+        stdout.write_all(b"\n")?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// Maps log positions onto the replay clock, which advances by the gaps
+/// between lines after scaling by the speed factor and capping
+struct Schedule {
+    first: Option<i128>,
+    previous: Option<i128>,
+    virtual_nanos: i128,
+    seek: i128,
+    max_gap: Option<i128>,
+    speed: f64,
+}
+
+impl Schedule {
+    /// Advance to the line at `position`. Returns true while the line is
+    /// still before the seek offset.
+    fn advance(&mut self, position: i128) -> bool {
+        let first = *self.first.get_or_insert(position);
+        let previous = self.previous.replace(position);
+        if position - first < self.seek {
+            return true;
+        }
+        // The first line after the seek point plays immediately
+        if let Some(prev) = previous.filter(|&prev| prev - first >= self.seek) {
+            let mut gap = ((position - prev).max(0) as f64 / self.speed) as i128;
+            if let Some(max_gap) = self.max_gap {
+                gap = gap.min(max_gap);
+            }
+            self.virtual_nanos += gap;
+        }
+        false
+    }
+
+    /// Time since the start of the replay at which the current line is due
+    fn due(&self) -> Duration {
+        Duration::from_nanos(self.virtual_nanos.max(0) as u64)
+    }
+}
+
+/// Extract the timestamp and line fields from a record written by --json
+fn split_json(line: &str) -> Option<(String, String)> {
+    let timestamp = json_field(line, "timestamp")?;
+    let text = json_field(line, "line")?;
+    Some((timestamp, text))
+}
+
+fn json_field(line: &str, name: &str) -> Option<String> {
+    let key = format!("\"{}\":\"", name);
+    let start = line.find(&key)? + key.len();
+    let mut out = String::new();
+    let mut chars = line[start..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    out.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
+                other => out.push(other),
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
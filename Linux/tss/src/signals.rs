@@ -0,0 +1,173 @@
+use crate::pty;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// Run on the signal thread for SIGHUP/SIGUSR1, one per output file
+static REOPENERS: Mutex<Vec<Box<dyn Fn() + Send>>> = Mutex::new(Vec::new());
+// Number of the SIGTERM/SIGINT that asked us to stop, or 0
+static TERMINATE: AtomicI32 = AtomicI32::new(0);
+// Command run without --pty that SIGTERM is passed on to, or 0
+static CHILD: AtomicI32 = AtomicI32::new(0);
+// Set once a signal reached the command, so a second one exits at once
+static FORWARDED: AtomicBool = AtomicBool::new(false);
+
+/// Route SIGHUP/SIGUSR1 (reopen the output files) and SIGTERM/SIGINT (stop
+/// reading and flush everything, see also `forward_to`) to a sigwait thread.
+/// With `relayed` those other signals go to a --pty child and only SIGUSR1
+/// is taken here, but the signals the pty relay forwards are blocked too.
+///
+/// Must run before any other thread is spawned so every thread inherits
+/// the blocked mask; otherwise a forwarded signal could land on a thread
+/// that does not wait for it and kill tss outright.
+///
+/// Returns the read end of a pipe that becomes readable once a termination
+/// signal arrives, for `Interruptible`.
+pub fn install(relayed: bool) -> io::Result<File> {
+    let signals: &[libc::c_int] = if relayed {
+        &[libc::SIGUSR1]
+    } else {
+        &[libc::SIGHUP, libc::SIGUSR1, libc::SIGTERM, libc::SIGINT]
+    };
+    let sigset = signal_set(signals);
+    let blocked = if relayed {
+        signal_set(&[&[libc::SIGUSR1][..], &pty::FORWARDED_SIGNALS[..]].concat())
+    } else {
+        sigset
+    };
+    // SAFETY: blocked is fully initialised
+    unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &blocked, ptr::null_mut()) };
+
+    let mut fds = [0; 2];
+    // SAFETY: fds has room for the two descriptors pipe2 writes
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: pipe2 returned two freshly opened descriptors we now own
+    let (wake, mut waker) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+    thread::spawn(move || loop {
+        let mut sig: libc::c_int = 0;
+        // SAFETY: sigset and sig are valid for the duration of the call
+        if unsafe { libc::sigwait(&sigset, &mut sig) } != 0 {
+            continue;
+        }
+        let child = CHILD.load(Ordering::Acquire);
+        if sig == libc::SIGHUP || sig == libc::SIGUSR1 {
+            for reopen in REOPENERS.lock().unwrap().iter() {
+                reopen();
+            }
+        } else if child != 0 {
+            // Keep reading until the command's output ends. SIGINT from the
+            // terminal already reached it, as it shares our process group.
+            if FORWARDED.swap(true, Ordering::AcqRel) {
+                // SAFETY: _exit has no memory safety requirements
+                unsafe { libc::_exit(128 + sig) };
+            }
+            if sig == libc::SIGTERM {
+                // SAFETY: kill has no memory safety requirements
+                unsafe { libc::kill(child, sig) };
+            }
+        } else if TERMINATE.swap(sig, Ordering::AcqRel) != 0 {
+            // A second interrupt while still flushing: give up at once
+            // SAFETY: _exit has no memory safety requirements
+            unsafe { libc::_exit(128 + sig) };
+        } else {
+            let _ = waker.write_all(b"x");
+        }
+    });
+    Ok(wake)
+}
+
+fn signal_set(signals: &[libc::c_int]) -> libc::sigset_t {
+    // SAFETY: sigemptyset initialises the set before any sigaddset
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for &sig in signals {
+            libc::sigaddset(&mut set, sig);
+        }
+        set
+    }
+}
+
+/// Start `command` with nothing blocked. The signals tss waits for are
+/// blocked in every thread, and an exec'd program inherits that mask, so
+/// without this a forwarded SIGTERM would stay pending in the child.
+pub fn unblock_in_child(command: &mut Command) {
+    // SAFETY: sigemptyset and sigprocmask are async-signal-safe
+    unsafe {
+        command.pre_exec(|| {
+            let mut set: libc::sigset_t = std::mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigprocmask(libc::SIG_SETMASK, &set, ptr::null_mut());
+            Ok(())
+        });
+    }
+}
+
+/// Pass SIGTERM on to the command `pid` instead of stopping; its pipes
+/// close when it exits and everything is flushed as usual
+pub fn forward_to(pid: u32) {
+    CHILD.store(pid as i32, Ordering::Release);
+}
+
+/// Have SIGHUP/SIGUSR1 call `reopen` right away, on the signal thread, so
+/// a rotated file is let go even while no lines arrive
+pub fn on_reopen(reopen: impl Fn() + Send + 'static) {
+    REOPENERS.lock().unwrap().push(Box::new(reopen));
+}
+
+/// The termination signal received, if any
+pub fn terminated() -> Option<i32> {
+    match TERMINATE.load(Ordering::Acquire) {
+        0 => None,
+        sig => Some(sig),
+    }
+}
+
+/// Reads `inner` until a termination signal arrives, then reports end of
+/// input so the line already buffered and every sink get flushed normally.
+pub struct Interruptible<R> {
+    inner: R,
+    fd: RawFd,
+    wake: File,
+}
+
+impl<R: Read + AsRawFd> Interruptible<R> {
+    pub fn new(inner: R, wake: File) -> Self {
+        let fd = inner.as_raw_fd();
+        Self { inner, fd, wake }
+    }
+}
+
+impl<R: Read> Read for Interruptible<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut fds = [
+            libc::pollfd { fd: self.fd, events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: self.wake.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+        ];
+        loop {
+            if terminated().is_some() {
+                return Ok(0);
+            }
+            // SAFETY: fds is valid for the duration of the call
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            if fds[1].revents == 0 {
+                return self.inner.read(buf);
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A complete record as handed to the structured sinks
+pub struct Record {
+    pub seq: u64,
+    pub time: SystemTime,
+    pub delta: Duration,
+    pub stream: Stream,
+    pub timestamp: String,
+    pub lines: Vec<String>,
+    pub upstream: Option<UpstreamStamp>,
+    // Completion time shown next to a first-byte timestamp
+    pub completed: Option<String>,
+    // Kept off the terminal by --match/--exclude
+    pub hidden: bool,
+}
+
+/// The stream a record was read from. Inputs are told apart by kind, not
+/// by name, so a file called stdout is not taken for a command's stdout.
+#[derive(Clone, PartialEq)]
+pub enum Stream {
+    // Our own stdin, without inputs on the command line
+    Stdin,
+    // A piped command's output, or a --pty command's
+    Stdout,
+    Stderr,
+    // A file, FIFO or fd from the command line, by name or --label
+    Input(Arc<str>),
+}
+
+impl Stream {
+    pub fn name(&self) -> &str {
+        match self {
+            Stream::Stdin => "stdin",
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+            Stream::Input(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The prefix chain an earlier tss put on a line, with --stamp-again
+pub struct UpstreamStamp {
+    pub prefix: String,
+    pub latency_ns: i64,
+}
+
+impl Record {
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn epoch_nanos(&self) -> i64 {
+        epoch_nanos(self.time)
+    }
+}
+
+/// Metadata about the whole run, passed to sinks when it starts and ends
+pub struct RunInfo {
+    pub id: String,
+    pub command: Option<String>,
+    pub host: String,
+    pub start: SystemTime,
+}
+
+impl RunInfo {
+    pub fn new(id: Option<String>, command: Option<String>) -> Self {
+        let start = SystemTime::now();
+        // Unique enough to tell runs apart: start time and pid
+        let id = id.unwrap_or_else(|| format!("{:x}-{:x}", epoch_nanos(start), std::process::id()));
+        Self {
+            id,
+            command,
+            host: hostname(),
+            start,
+        }
+    }
+}
+
+/// A destination that receives every record in addition to stdout
+pub trait Sink {
+    fn write_record(&mut self, record: &Record) -> io::Result<()>;
+
+    /// Called once after the last record, with the exit code of the
+    /// wrapped command if there is one
+    fn finish(&mut self, end: SystemTime, exit_code: Option<i32>) -> io::Result<()>;
+}
+
+pub fn epoch_nanos(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or_default()
+}
+
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    // SAFETY: gethostname writes at most buf.len() bytes into buf
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if ret != 0 {
+        return "localhost".to_string();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
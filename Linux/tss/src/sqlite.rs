@@ -0,0 +1,148 @@
+use crate::level::Level;
+use crate::sink::{epoch_nanos, Record, RunInfo, Sink};
+use rusqlite::{params, Connection};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// Commit after this many lines or this much time, whichever comes first
+const BATCH_LINES: usize = 1000;
+const BATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id        INTEGER PRIMARY KEY,
+    run_id    TEXT,
+    command   TEXT,
+    start_ns  INTEGER NOT NULL,
+    end_ns    INTEGER,
+    exit_code INTEGER
+);
+CREATE INDEX IF NOT EXISTS runs_run_id ON runs (run_id);
+CREATE TABLE IF NOT EXISTS lines (
+    run      INTEGER NOT NULL REFERENCES runs(id),
+    seq      INTEGER NOT NULL,
+    epoch_ns INTEGER NOT NULL,
+    delta_ns INTEGER NOT NULL,
+    stream   TEXT NOT NULL,
+    level    TEXT,
+    text     TEXT NOT NULL,
+    PRIMARY KEY (run, seq)
+);
+";
+
+/// Inserts every record into a local SQLite database, one row per record
+/// in `lines` and one row per invocation in `runs`. A background thread
+/// commits a pending batch once BATCH_INTERVAL has passed, so readers see
+/// the last lines of a stream that has gone quiet.
+pub struct SqliteSink {
+    shared: Arc<Mutex<Batch>>,
+    // Row of this invocation in runs
+    run: i64,
+}
+
+/// The connection and its open transaction, shared with the commit thread
+struct Batch {
+    conn: Connection,
+    batched: usize,
+    batch_started: Instant,
+    done: bool,
+}
+
+impl SqliteSink {
+    pub fn open(path: &str, run: &RunInfo) -> io::Result<Self> {
+        let conn = Connection::open(path).map_err(to_io)?;
+        conn.execute_batch(SCHEMA).map_err(to_io)?;
+        conn.execute(
+            "INSERT INTO runs (run_id, command, start_ns) VALUES (?1, ?2, ?3)",
+            params![run.id, run.command, epoch_nanos(run.start)],
+        )
+        .map_err(to_io)?;
+        let run = conn.last_insert_rowid();
+        conn.execute_batch("BEGIN").map_err(to_io)?;
+
+        let shared = Arc::new(Mutex::new(Batch {
+            conn,
+            batched: 0,
+            batch_started: Instant::now(),
+            done: false,
+        }));
+        let timer = Arc::clone(&shared);
+        thread::spawn(move || loop {
+            thread::sleep(BATCH_INTERVAL / 4);
+            let mut batch = timer.lock().unwrap();
+            if batch.done {
+                return;
+            }
+            if batch.batched > 0 && batch.batch_started.elapsed() >= BATCH_INTERVAL {
+                if let Err(e) = batch.commit() {
+                    eprintln!("tss: {}", e);
+                }
+            }
+        });
+
+        Ok(Self { shared, run })
+    }
+}
+
+impl Batch {
+    fn commit(&mut self) -> io::Result<()> {
+        self.conn.execute_batch("COMMIT; BEGIN").map_err(to_io)?;
+        self.batched = 0;
+        self.batch_started = Instant::now();
+        Ok(())
+    }
+}
+
+impl Sink for SqliteSink {
+    fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        let text = record.text();
+        let level = Level::detect(&text).map(Level::as_str);
+        let mut batch = self.shared.lock().unwrap();
+        batch
+            .conn
+            .prepare_cached(
+                "INSERT INTO lines (run, seq, epoch_ns, delta_ns, stream, level, text)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )
+            .and_then(|mut stmt| {
+                stmt.execute(params![
+                    self.run,
+                    record.seq as i64,
+                    record.epoch_nanos(),
+                    record.delta.as_nanos() as i64,
+                    record.stream.name(),
+                    level,
+                    text,
+                ])
+            })
+            .map_err(to_io)?;
+
+        if batch.batched == 0 {
+            batch.batch_started = Instant::now();
+        }
+        batch.batched += 1;
+        if batch.batched >= BATCH_LINES || batch.batch_started.elapsed() >= BATCH_INTERVAL {
+            batch.commit()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, end: SystemTime, exit_code: Option<i32>) -> io::Result<()> {
+        let mut batch = self.shared.lock().unwrap();
+        batch.done = true;
+        batch
+            .conn
+            .execute(
+                "UPDATE runs SET end_ns = ?1, exit_code = ?2 WHERE id = ?3",
+                params![epoch_nanos(end), exit_code, self.run],
+            )
+            .map_err(to_io)?;
+        batch.conn.execute_batch("COMMIT").map_err(to_io)
+    }
+}
+
+fn to_io(e: rusqlite::Error) -> io::Error {
+    io::Error::other(format!("sqlite: {}", e))
+}
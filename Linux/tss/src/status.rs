@@ -0,0 +1,176 @@
+use crate::theme::strip_escapes;
+use std::collections::VecDeque;
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+// Lines per second are measured over this much recent history
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// A single line on the stderr terminal, redrawn in place a few times a
+/// second: elapsed time, lines, lines per second, time since the last line
+/// and the last line itself, cut to the terminal width.
+#[derive(Clone)]
+pub struct Status {
+    state: Arc<Mutex<State>>,
+}
+
+/// Keeps the status line off the screen while held
+pub struct Hidden<'a> {
+    _guard: MutexGuard<'a, State>,
+}
+
+struct State {
+    start: Instant,
+    lines: u64,
+    last_line: String,
+    last_at: Option<Instant>,
+    // (time, lines) at each redraw within RATE_WINDOW
+    samples: VecDeque<(Instant, u64)>,
+    // Characters on screen and the width they were drawn at
+    drawn: usize,
+    drawn_width: usize,
+    done: bool,
+}
+
+impl Status {
+    /// Start drawing, if stderr is a terminal that stdout is not writing to
+    pub fn start() -> Option<Self> {
+        if !io::stderr().is_terminal() {
+            return None;
+        }
+        if io::stdout().is_terminal() {
+            eprintln!("tss: --status is only shown while stdout goes to a file or pipe");
+            return None;
+        }
+        let state = Arc::new(Mutex::new(State {
+            start: Instant::now(),
+            lines: 0,
+            last_line: String::new(),
+            last_at: None,
+            samples: VecDeque::new(),
+            drawn: 0,
+            drawn_width: 0,
+            done: false,
+        }));
+        let shared = Arc::clone(&state);
+        thread::spawn(move || loop {
+            thread::sleep(REDRAW_INTERVAL);
+            let mut state = shared.lock().unwrap();
+            if state.done {
+                return;
+            }
+            state.draw();
+        });
+        Some(Self { state })
+    }
+
+    pub fn line(&self, text: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.lines += 1;
+        state.last_at = Some(Instant::now());
+        state.last_line.clear();
+        state.last_line.push_str(text);
+    }
+
+    /// Take the status line off the screen until the guard is dropped, so
+    /// something else can be written to stderr; it is redrawn on the next
+    /// tick
+    pub fn hide(&self) -> Hidden<'_> {
+        let mut state = self.state.lock().unwrap();
+        state.erase();
+        Hidden { _guard: state }
+    }
+
+    /// Remove the status line for good
+    pub fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.erase();
+        state.done = true;
+    }
+}
+
+impl State {
+    fn draw(&mut self) {
+        let now = Instant::now();
+        self.samples.push_back((now, self.lines));
+        while self.samples.front().is_some_and(|&(at, _)| now - at > RATE_WINDOW) {
+            self.samples.pop_front();
+        }
+        let rate = match self.samples.front() {
+            Some(&(at, lines)) if now - at >= REDRAW_INTERVAL => {
+                (self.lines - lines) as f64 / (now - at).as_secs_f64()
+            }
+            _ => 0.0,
+        };
+
+        let mut text = format!(
+            "{}  {} lines  {:.1}/s",
+            format_elapsed(now - self.start),
+            self.lines,
+            rate
+        );
+        if let Some(last_at) = self.last_at {
+            text.push_str(&format!("  last {:.1}s ago  | ", (now - last_at).as_secs_f64()));
+            let line = strip_escapes(&self.last_line);
+            text.extend(line.chars().map(|c| if c.is_control() { ' ' } else { c }));
+        }
+
+        // One column short of the width, so the cursor never wraps
+        let width = terminal_width();
+        let shown: String = text.chars().take(width.saturating_sub(1)).collect();
+        self.erase();
+        let mut stderr = io::stderr().lock();
+        let _ = stderr.write_all(shown.as_bytes());
+        let _ = stderr.flush();
+        self.drawn = shown.chars().count();
+        self.drawn_width = width;
+    }
+
+    /// Clear what was drawn, including the rows a terminal that reflows
+    /// on resize has wrapped it onto after shrinking
+    fn erase(&mut self) {
+        if self.drawn == 0 {
+            return;
+        }
+        let width = terminal_width();
+        let rows = if width < self.drawn_width {
+            self.drawn.saturating_sub(1) / width.max(1)
+        } else {
+            0
+        };
+        let mut seq = String::new();
+        if rows > 0 {
+            seq.push_str(&format!("\x1b[{}A", rows));
+        }
+        seq.push_str("\r\x1b[J");
+        let mut stderr = io::stderr().lock();
+        let _ = stderr.write_all(seq.as_bytes());
+        let _ = stderr.flush();
+        self.drawn = 0;
+    }
+}
+
+/// Elapsed time as M:SS, or H:MM:SS from an hour on
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+fn terminal_width() -> usize {
+    // SAFETY: winsize is plain data and TIOCGWINSZ only writes into it
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut ws) };
+    if ret == 0 && ws.ws_col > 0 {
+        ws.ws_col as usize
+    } else {
+        80
+    }
+}
@@ -0,0 +1,243 @@
+use std::env;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const RESET: &str = "\x1b[0m";
+
+// Deltas at or above these get the matching heat color with --delta
+const HEAT_THRESHOLDS: [Duration; 3] = [
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(ColorMode::Auto),
+            "always" => Some(ColorMode::Always),
+            "never" => Some(ColorMode::Never),
+            _ => None,
+        }
+    }
+
+    /// Resolve the mode against the environment. An explicit always or
+    /// never wins; auto honours FORCE_COLOR, then NO_COLOR, then checks that
+    /// stdout is a terminal that is not "dumb".
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                if env_set("FORCE_COLOR").is_some_and(|v| v != "0" && v != "false") {
+                    return true;
+                }
+                if env_set("NO_COLOR").is_some() {
+                    return false;
+                }
+                std::io::stdout().is_terminal() && env::var("TERM").map_or(true, |term| term != "dumb")
+            }
+        }
+    }
+}
+
+/// SGR sequences for each colored element of the output
+pub struct Theme {
+    pub timestamp: String,
+    pub label: String,
+    pub heat: [String; 3],
+}
+
+impl Theme {
+    /// The default theme, overridden by the theme file and then by
+    /// TSS_THEME, both holding key=color pairs
+    pub fn load() -> Result<Self, String> {
+        let truecolor = truecolor();
+        let mut theme = Theme {
+            timestamp: sgr("cyan", truecolor)?,
+            label: sgr("magenta", truecolor)?,
+            heat: [sgr("yellow", truecolor)?, sgr("208", truecolor)?, sgr("bold+red", truecolor)?],
+        };
+        if let Some(path) = theme_file() {
+            if let Ok(spec) = fs::read_to_string(&path) {
+                theme
+                    .apply(&spec, truecolor)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+        }
+        if let Some(spec) = env_set("TSS_THEME") {
+            theme.apply(&spec, truecolor).map_err(|e| format!("TSS_THEME: {}", e))?;
+        }
+        Ok(theme)
+    }
+
+    /// Color for a timestamp shown with --delta, by the size of the delta
+    pub fn heat(&self, delta: Duration) -> &str {
+        match HEAT_THRESHOLDS.iter().rposition(|&threshold| delta >= threshold) {
+            Some(level) => &self.heat[level],
+            None => &self.timestamp,
+        }
+    }
+
+    /// Apply "key=color" entries separated by ':' or newlines; lines
+    /// starting with '#' are comments
+    fn apply(&mut self, spec: &str, truecolor: bool) -> Result<(), String> {
+        let entries = spec
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split(':'))
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty());
+        for entry in entries {
+            let (key, value) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=color, got '{}'", entry))?;
+            match key.trim() {
+                "timestamp" => self.timestamp = sgr(value.trim(), truecolor)?,
+                "label" => self.label = sgr(value.trim(), truecolor)?,
+                "heat" => {
+                    let colors: Vec<&str> = value.split(',').map(str::trim).collect();
+                    if colors.len() != 3 {
+                        return Err(format!("heat takes three comma-separated colors, got '{}'", value));
+                    }
+                    for (slot, color) in self.heat.iter_mut().zip(colors) {
+                        *slot = sgr(color, truecolor)?;
+                    }
+                }
+                other => return Err(format!("unknown key '{}' (expected timestamp, label or heat)", other)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// SGR sequence for a color spec given on the command line, e.g. for
+/// --highlight
+pub fn color(spec: &str) -> Result<String, String> {
+    sgr(spec, truecolor())
+}
+
+fn truecolor() -> bool {
+    env::var("COLORTERM").is_ok_and(|v| v == "truecolor" || v == "24bit")
+}
+
+/// Build the SGR sequence for a color spec: attributes and one color joined
+/// with '+', e.g. "bold+red", "208" (256-color) or "#ff8800" (truecolor,
+/// approximated in 256 colors unless the terminal advertises truecolor).
+/// "none" yields an empty sequence.
+fn sgr(spec: &str, truecolor: bool) -> Result<String, String> {
+    let mut codes = Vec::new();
+    for part in spec.split('+').map(str::trim) {
+        let code = match part {
+            "none" | "default" => continue,
+            "bold" => "1".to_string(),
+            "dim" => "2".to_string(),
+            "italic" => "3".to_string(),
+            "underline" => "4".to_string(),
+            _ => color_code(part, truecolor).ok_or_else(|| format!("unknown color '{}'", part))?,
+        };
+        codes.push(code);
+    }
+    if codes.is_empty() {
+        return Ok(String::new());
+    }
+    Ok(format!("\x1b[{}m", codes.join(";")))
+}
+
+fn color_code(color: &str, truecolor: bool) -> Option<String> {
+    const NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+    if let Some(index) = NAMES.iter().position(|&name| name == color) {
+        return Some((30 + index).to_string());
+    }
+    if let Some(index) = color
+        .strip_prefix("bright-")
+        .and_then(|name| NAMES.iter().position(|&n| n == name))
+    {
+        return Some((90 + index).to_string());
+    }
+    if let Ok(index) = color.parse::<u8>() {
+        return Some(format!("38;5;{}", index));
+    }
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    let (r, g, b) = ((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8);
+    if truecolor {
+        Some(format!("38;2;{};{};{}", r, g, b))
+    } else {
+        Some(format!("38;5;{}", to_256(r, g, b)))
+    }
+}
+
+/// Nearest entry in the 6x6x6 color cube or the grayscale ramp of the
+/// 256-color palette
+fn to_256(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let nearest = |v: u8| {
+        (0..6)
+            .min_by_key(|&i| (LEVELS[i] as i32 - v as i32).abs())
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (nearest(r), nearest(g), nearest(b));
+    let cube = 16 + 36 * ri + 6 * gi + bi;
+    let cube_dist = dist((LEVELS[ri], LEVELS[gi], LEVELS[bi]), (r, g, b));
+
+    let avg = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_index = ((avg.saturating_sub(8)) / 10).min(23) as u8;
+    let gray = 8 + 10 * gray_index;
+    if dist((gray, gray, gray), (r, g, b)) < cube_dist {
+        232 + gray_index
+    } else {
+        cube as u8
+    }
+}
+
+fn dist(a: (u8, u8, u8), b: (u8, u8, u8)) -> i32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2);
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// Drop terminal escape sequences, which a --pty command is likely to emit,
+/// for the HTML report and output files written with ,plain
+pub fn strip_escapes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        // CSI: ESC [ parameters, then a final byte in @..~
+        if chars.clone().next() == Some('[') {
+            chars.next();
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            chars.next();
+        }
+    }
+    out
+}
+
+fn theme_file() -> Option<PathBuf> {
+    let base = env_set("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env_set("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("tss").join("theme"))
+}
+
+/// An environment variable that is set and not empty
+fn env_set(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
@@ -0,0 +1,100 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+const INPUT: &str = "first\n  indented second\n\nlast\n";
+
+/// Run tss with `args`, feeding it `input`
+fn tss(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tss"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "tss {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+/// Stamp INPUT into a log file with `options` and a banner, and return
+/// the path of the log
+fn write_log(name: &str, options: &[&str]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("tss-roundtrip-{}-{}.log", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    let mut args = options.to_vec();
+    args.extend(["--banner", "-o", path.to_str().unwrap()]);
+    tss(&args, INPUT.as_bytes());
+    path
+}
+
+fn replay(path: &Path, options: &[&str]) -> String {
+    let mut args = vec!["replay", "--max-gap", "0"];
+    args.extend(options);
+    args.push(path.to_str().unwrap());
+    String::from_utf8(tss(&args, b"").stdout).unwrap()
+}
+
+#[test]
+fn replay_restores_the_original_lines() {
+    for (name, options) in [
+        ("default", &[][..]),
+        ("millis", &["-f", "%Y-%m-%d %H:%M:%S%.3f"][..]),
+        ("iso", &["--iso"][..]),
+        ("epoch", &["-e"][..]),
+        ("separator", &["-s", " | "][..]),
+    ] {
+        let path = write_log(name, options);
+        let replayed = replay(&path, options);
+        let _ = fs::remove_file(&path);
+        assert_eq!(replayed, INPUT, "{}", name);
+    }
+}
+
+#[test]
+fn replay_reads_json_logs() {
+    let path = write_log("json", &["--json"]);
+    let replayed = replay(&path, &["--json"]);
+    let _ = fs::remove_file(&path);
+    assert_eq!(replayed, INPUT);
+}
+
+#[test]
+fn stamp_again_extends_each_prefix_and_copies_banners() {
+    let path = write_log("again", &[]);
+    let log = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    let output = String::from_utf8(tss(&["--stamp-again"], log.as_bytes()).stdout).unwrap();
+
+    let upstream: Vec<&str> = log.lines().collect();
+    let restamped: Vec<&str> = output.lines().collect();
+    assert_eq!(restamped.len(), upstream.len());
+    assert!(upstream[0].starts_with("# tss "));
+    assert_eq!(restamped[0], upstream[0]);
+    assert_eq!(restamped.last(), upstream.last());
+
+    let lines = &upstream[1..upstream.len() - 1];
+    for ((restamped, upstream), text) in restamped[1..].iter().zip(lines).zip(INPUT.lines()) {
+        // UPSTREAM OURS +LATENCY TEXT, with the default 19 character prefix
+        let (prefix, rest) = upstream.split_at(19);
+        assert_eq!(&rest[1..], text);
+        let rest = restamped.strip_prefix(prefix).unwrap();
+        let (latency, restamped_text) = rest[21..].split_once(' ').unwrap();
+        assert!(latency.starts_with('+'), "{}", restamped);
+        assert_eq!(restamped_text, text);
+    }
+}
+
+#[test]
+fn stamp_again_dedupes_on_the_text_after_the_upstream_prefix() {
+    let upstream = "2026-10-19 05:00:00 same\n2026-10-19 05:00:01 same\n2026-10-19 05:00:02 same\n2026-10-19 05:00:03 other\n";
+    let output = String::from_utf8(tss(&["--stamp-again", "--dedupe"], upstream.as_bytes()).stdout).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", output);
+    assert!(lines[0].starts_with("2026-10-19 05:00:00 ") && lines[0].ends_with(" same"));
+    assert!(lines[1].contains("last message repeated 2 times"), "{}", lines[1]);
+    assert!(lines[2].starts_with("2026-10-19 05:00:03 ") && lines[2].ends_with(" other"));
+}
@@ -0,0 +1,57 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn alive(pid: libc::pid_t) -> bool {
+    // SAFETY: signal 0 only checks that the process exists
+    unsafe { libc::kill(pid, 0) == 0 }
+}
+
+/// Run a command under tss, SIGTERM tss once the command is up, and check
+/// that the command and then tss exit promptly
+fn sigterm_stops_the_child(mode: &[&str]) {
+    let mut tss = Command::new(env!("CARGO_BIN_EXE_tss"))
+        .args(["-f", "%s"])
+        .args(mode)
+        .args(["--", "sh", "-c", "echo $$; exec sleep 30"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(tss.stdout.take().unwrap());
+    let mut first = String::new();
+    stdout.read_line(&mut first).unwrap();
+    let child: libc::pid_t = first.split_whitespace().last().unwrap().parse().unwrap();
+    assert!(alive(child));
+
+    // SAFETY: kill has no memory safety requirements
+    unsafe { libc::kill(tss.id() as libc::pid_t, libc::SIGTERM) };
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut status = None;
+    while Instant::now() < deadline {
+        status = tss.try_wait().unwrap();
+        if status.is_some() && !alive(child) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    let orphaned = alive(child);
+    // SAFETY: kill has no memory safety requirements
+    unsafe { libc::kill(child, libc::SIGKILL) };
+    let _ = tss.kill();
+    let _ = tss.wait();
+    assert!(!orphaned, "child {} is still running", child);
+    assert!(status.is_some(), "tss is still running");
+}
+
+// SIGTERM must reach the --pty child rather than kill tss on whichever
+// thread happens to receive it
+#[test]
+fn sigterm_with_pty_stops_the_child() {
+    sigterm_stops_the_child(&["--pty"]);
+}
+
+#[test]
+fn sigterm_is_passed_on_to_a_piped_command() {
+    sigterm_stops_the_child(&[]);
+}